@@ -0,0 +1,74 @@
+//! 提供有序区间实现的分配器 [`IntervalAllocator`]
+
+use super::Allocator;
+use alloc::collections::BTreeMap;
+
+/// 使用按起始位置排序的空闲区间实现分配器
+///
+/// 每一项 `start -> end` 表示 [start, end) 区间为可用。
+/// 回收时与前后相邻的区间合并，因此逐个回收的连续元素会重新成为一整个区间，
+/// 连续分配在释放之后仍然可以成功，区间的个数也不会随着回收不断增长。
+///
+/// 分配和回收的时间复杂度为 O(log n)，连续分配需要查找合适的区间，为 O(n)，n 为空闲区间的个数
+pub struct IntervalAllocator {
+    free: BTreeMap<usize, usize>,
+}
+
+impl Allocator for IntervalAllocator {
+    fn new(capacity: usize) -> Self {
+        let mut free = BTreeMap::new();
+        if capacity > 0 {
+            free.insert(0, capacity);
+        }
+        Self { free }
+    }
+
+    fn alloc(&mut self) -> Option<usize> {
+        // 从最后一个区间的末尾分配，区间的起始位置不变，只需修改结束位置
+        let (&start, end) = self.free.iter_mut().next_back()?;
+        *end -= 1;
+        let index = *end;
+        if index == start {
+            self.free.remove(&start);
+        }
+        Some(index)
+    }
+
+    fn alloc_contiguous(&mut self, count: usize, align: usize, offset: usize) -> Option<usize> {
+        let (start, end, aligned) = self.free.iter().find_map(|(&start, &end)| {
+            // 区间中满足对齐要求的最小起始位置
+            let aligned = (start + offset + align - 1) / align * align - offset;
+            if aligned + count <= end {
+                Some((start, end, aligned))
+            } else {
+                None
+            }
+        })?;
+        // 区间剩余的前后两部分重新加入
+        self.free.remove(&start);
+        if start < aligned {
+            self.free.insert(start, aligned);
+        }
+        if aligned + count < end {
+            self.free.insert(aligned + count, end);
+        }
+        Some(aligned)
+    }
+
+    fn dealloc(&mut self, index: usize) {
+        let mut start = index;
+        let mut end = index + 1;
+        // 与前一个区间相邻时合并
+        if let Some((&prev_start, &prev_end)) = self.free.range(..index).next_back() {
+            if prev_end == index {
+                self.free.remove(&prev_start);
+                start = prev_start;
+            }
+        }
+        // 与后一个区间相邻时合并
+        if let Some(next_end) = self.free.remove(&end) {
+            end = next_end;
+        }
+        self.free.insert(start, end);
+    }
+}
@@ -1,6 +1,7 @@
 //! 负责分配 / 回收的数据结构
 
 mod stacked_allocator;
+mod interval_allocator;
 mod segtree_allocator;
 mod bitmap_vector_allocator;
 
@@ -12,6 +13,12 @@ pub trait Allocator {
     fn alloc(&mut self) -> Option<usize>;
     /// 回收一个元素
     fn dealloc(&mut self, index: usize);
+    /// 分配 `count` 个连续的元素，且 `起始下标 + offset` 是 `align` 的倍数，无法分配则返回 `None`
+    ///
+    /// 分配的元素之后逐个回收。默认不支持连续分配
+    fn alloc_contiguous(&mut self, _count: usize, _align: usize, _offset: usize) -> Option<usize> {
+        None
+    }
 }
 
 /// 向量分配器：固定容量，每次分配 / 回收一个带有对齐要求的连续向量
@@ -27,11 +34,13 @@ pub trait VectorAllocator {
 }
 
 pub use stacked_allocator::StackedAllocator;     // 栈式   单个页面分配
+pub use interval_allocator::IntervalAllocator;   // 有序区间 回收时合并相邻区间，支持连续分配
 pub use segtree_allocator::SegmentTreeAllocator; // 线段树 单个页面分配
 pub use bitmap_vector_allocator::BitmapVectorAllocator;
 
 
 /// 默认使用的分配器
-pub type AllocatorImpl = StackedAllocator;
+pub type AllocatorImpl = IntervalAllocator;
+//pub type AllocatorImpl = StackedAllocator;
 //pub type AllocatorImpl = SegmentTreeAllocator;
 pub type VectorAllocatorImpl = BitmapVectorAllocator;
@@ -35,6 +35,26 @@ impl Allocator for StackedAllocator {
         }
     }
 
+    fn alloc_contiguous(&mut self, count: usize, align: usize, offset: usize) -> Option<usize> { // O(n)
+        for i in 0..self.list.len() {
+            let (start, end) = self.list[i];
+            // 区间中满足对齐要求的最小起始位置
+            let aligned = (start + offset + align - 1) / align * align - offset;
+            if aligned + count <= end {
+                // 区间剩余的前后两部分重新压入栈
+                self.list.swap_remove(i);
+                if start < aligned {
+                    self.list.push((start, aligned));
+                }
+                if aligned + count < end {
+                    self.list.push((aligned + count, end));
+                }
+                return Some(aligned);
+            }
+        }
+        None
+    }
+
     fn dealloc(&mut self, index: usize) { // O(1)
         self.list.push((index, index + 1)); // index开始的空间一定是[index, index+1), 所以直接压回即可（因为申请的粒度也是1，所以可以直接放在栈顶，下次也只需要分配栈顶这个）
     }
@@ -4,15 +4,34 @@
 
 use super::bus::virtio_mmio::virtio_probe;
 use crate::memory::VirtualAddress;
+use alloc::string::{String, ToString};
 use core::slice;
 use device_tree::{DeviceTree, Node};
+use lazy_static::lazy_static;
+use spin::RwLock;
 
 /// 验证某内存段为设备树格式的 Magic Number（固定）
 const DEVICE_TREE_MAGIC: u32 = 0xd00d_feed;
 
+lazy_static! {
+    /// `/chosen` 节点中的内核启动参数，QEMU 通过 `-append` 设置
+    static ref BOOTARGS: RwLock<String> = RwLock::new(String::new());
+}
+
+/// 内核启动参数，以空格分隔，例如 `init=/init`
+pub fn bootargs() -> String {
+    BOOTARGS.read().clone()
+}
+
 /// 递归遍历设备树
 /// 遍历过程中，一旦发现了一个支持 "virtio,mmio" 的设备（其实就是 QEMU 模拟的存储设备），就进入下一步加载驱动的逻辑。
 fn walk(node: &Node) {
+    // 记录启动参数
+    if node.name == "chosen" {
+        if let Ok(bootargs) = node.prop_str("bootargs") {
+            *BOOTARGS.write() = bootargs.to_string();
+        }
+    }
     // 检查设备的协议支持并初始化
     if let Ok(compatible) = node.prop_str("compatible") {
         if compatible == "virtio,mmio" {
@@ -0,0 +1,133 @@
+//! 内核中的错误 [`KernelError`]
+//!
+//! 内存、文件系统、设备和进程管理中的错误都可以转换为 [`KernelError`]，
+//! 系统调用返回时由 [`KernelError::errno`] 统一转换为负的错误码，使用户程序能够区分错误的原因。
+
+use crate::memory::ElfError;
+use rcore_fs::{dev::DevError, vfs::FsError};
+
+/// 一个缩写，内核中可能失败的操作返回这个类型
+pub type KernelResult<T> = Result<T, KernelError>;
+
+/// 内核中出现的错误
+#[derive(Debug)]
+pub enum KernelError {
+    /// 内存管理出错，例如内存不足或者地址空间中没有空闲的区间
+    Memory(&'static str),
+    /// 用户传入的地址不合法
+    Fault,
+    /// 参数不合法
+    InvalidArgument,
+    /// 文件描述符不存在
+    BadDescriptor,
+    /// 没有符合条件的子进程
+    NoChild,
+    /// 参数和环境变量过多
+    ArgumentListTooLong,
+    /// 不是可以执行的程序
+    NotExecutable,
+    /// 文件不是终端
+    NotTerminal,
+    /// 文件正被运行中的程序映射，不能写入
+    TextBusy,
+    /// 系统调用或功能没有实现
+    Unsupported,
+    /// 文件系统出错
+    Fs(FsError),
+    /// 设备读写出错
+    Device(DevError),
+}
+
+/// Linux 的错误码，系统调用返回它们的相反数
+mod errno {
+    pub const ENOENT: isize = 2;
+    pub const EINTR: isize = 4;
+    pub const EIO: isize = 5;
+    pub const E2BIG: isize = 7;
+    pub const ENOEXEC: isize = 8;
+    pub const EBADF: isize = 9;
+    pub const ECHILD: isize = 10;
+    pub const EAGAIN: isize = 11;
+    pub const ENOMEM: isize = 12;
+    pub const EFAULT: isize = 14;
+    pub const EBUSY: isize = 16;
+    pub const EEXIST: isize = 17;
+    pub const EXDEV: isize = 18;
+    pub const ENODEV: isize = 19;
+    pub const ENOTDIR: isize = 20;
+    pub const EISDIR: isize = 21;
+    pub const EINVAL: isize = 22;
+    pub const ENOTTY: isize = 25;
+    pub const ETXTBSY: isize = 26;
+    pub const ENOSPC: isize = 28;
+    pub const ENOSYS: isize = 38;
+    pub const ENOTEMPTY: isize = 39;
+}
+
+impl KernelError {
+    /// 系统调用返回给用户的错误码，为负数
+    pub fn errno(&self) -> isize {
+        use errno::*;
+        let errno = match self {
+            KernelError::Memory(_) => ENOMEM,
+            KernelError::Fault => EFAULT,
+            KernelError::InvalidArgument => EINVAL,
+            KernelError::BadDescriptor => EBADF,
+            KernelError::NoChild => ECHILD,
+            KernelError::ArgumentListTooLong => E2BIG,
+            KernelError::NotExecutable => ENOEXEC,
+            KernelError::NotTerminal => ENOTTY,
+            KernelError::TextBusy => ETXTBSY,
+            KernelError::Unsupported => ENOSYS,
+            KernelError::Fs(error) => match error {
+                FsError::NotSupported => ENOSYS,
+                FsError::IsDir => EISDIR,
+                FsError::NotDir => ENOTDIR,
+                FsError::EntryNotFound | FsError::DirRemoved => ENOENT,
+                FsError::EntryExist => EEXIST,
+                FsError::NotSameFs => EXDEV,
+                // 设备等不是普通文件的节点不支持这个操作
+                FsError::NotFile | FsError::InvalidParam | FsError::WrongFs => EINVAL,
+                FsError::NoDeviceSpace => ENOSPC,
+                FsError::DirNotEmpty => ENOTEMPTY,
+                FsError::NoDevice => ENODEV,
+                FsError::Busy => EBUSY,
+                FsError::Again => EAGAIN,
+                FsError::Interrupted => EINTR,
+                _ => EIO,
+            },
+            KernelError::Device(_) => EIO,
+        };
+        -errno
+    }
+}
+
+/// [`MemoryResult`](crate::memory::MemoryResult) 中的错误
+impl From<&'static str> for KernelError {
+    fn from(message: &'static str) -> Self {
+        KernelError::Memory(message)
+    }
+}
+
+impl From<FsError> for KernelError {
+    fn from(error: FsError) -> Self {
+        KernelError::Fs(error)
+    }
+}
+
+impl From<DevError> for KernelError {
+    fn from(error: DevError) -> Self {
+        KernelError::Device(error)
+    }
+}
+
+/// 读取文件和分配内存的错误保留原因，其他错误都表示文件不能执行
+impl From<ElfError> for KernelError {
+    fn from(error: ElfError) -> Self {
+        match error {
+            ElfError::Io(error) => KernelError::Fs(error),
+            ElfError::Memory(message) => KernelError::Memory(message),
+            _ => KernelError::NotExecutable,
+        }
+    }
+}
@@ -0,0 +1,186 @@
+//! 写回式块缓存 [`BlockCache`]
+//!
+//! 缓存块设备上最近使用的 [`BLOCK_CACHE_CAPACITY`] 个块，缓存满时换出最久未使用的块。
+//! 写入只修改缓存并将块标记为脏，在块被换出、调用 [`BlockDevice::sync`] 或关机时才写回设备。
+//! 将 [`BLOCK_CACHE_WRITE_BACK`] 设为 `false` 可以改为写直达。
+
+use super::*;
+use alloc::{sync::Weak, vec};
+use rcore_fs::dev::{self, BlockDevice};
+
+lazy_static! {
+    /// 所有块缓存，用于在关机时写回
+    static ref BLOCK_CACHES: Mutex<Vec<Weak<dyn TryFlush>>> = Mutex::new(Vec::new());
+}
+
+/// 可以在不阻塞的情况下尝试写回的缓存
+trait TryFlush: Send + Sync {
+    /// 尝试写回所有脏块，缓存正在被使用时返回 `false`
+    fn try_flush(&self) -> bool;
+}
+
+/// 一个缓存块
+struct CacheLine {
+    /// 对应的块号
+    block_id: usize,
+    /// 块中的数据
+    data: Vec<u8>,
+    /// 是否被修改过，尚未写回设备
+    dirty: bool,
+    /// 最近一次使用的时间，用于选择换出的块
+    last_used: usize,
+}
+
+/// 块缓存中需要可变的部分
+struct BlockCacheInner {
+    /// 所有缓存块
+    lines: Vec<CacheLine>,
+    /// 每次访问时递增的计数
+    clock: usize,
+}
+
+/// 写回式块缓存
+pub struct BlockCache<T: BlockDevice> {
+    /// 被缓存的设备
+    device: T,
+    /// 最多缓存的块数
+    capacity: usize,
+    /// 用 `Mutex` 包装可变的部分
+    inner: Mutex<BlockCacheInner>,
+}
+
+impl<T: BlockDevice + 'static> BlockCache<T> {
+    /// 为设备创建块缓存，并登记以便关机时写回
+    pub fn new(device: T, capacity: usize) -> Arc<Self> {
+        assert!(capacity > 0);
+        let cache = Arc::new(Self {
+            device,
+            capacity,
+            inner: Mutex::new(BlockCacheInner {
+                lines: Vec::with_capacity(capacity),
+                clock: 0,
+            }),
+        });
+        let weak: Weak<dyn TryFlush> = Arc::downgrade(&cache) as _;
+        let mut caches = BLOCK_CACHES.lock();
+        caches.retain(|cache| cache.strong_count() > 0);
+        caches.push(weak);
+        cache
+    }
+}
+
+impl<T: BlockDevice> BlockCache<T> {
+    /// 块大小
+    const BLOCK_SIZE: usize = 1 << T::BLOCK_SIZE_LOG2;
+
+    /// 找到 `block_id` 对应的缓存块，不存在时分配一个
+    ///
+    /// 缓存已满时换出最久未使用的块；`load` 为 `true` 时从设备读入块的内容
+    fn get_line(&self, inner: &mut BlockCacheInner, block_id: usize, load: bool) -> dev::Result<usize> {
+        inner.clock += 1;
+        let clock = inner.clock;
+        if let Some(index) = inner.lines.iter().position(|line| line.block_id == block_id) {
+            inner.lines[index].last_used = clock;
+            return Ok(index);
+        }
+        let index = if inner.lines.len() < self.capacity {
+            inner.lines.push(CacheLine {
+                block_id,
+                data: vec![0; Self::BLOCK_SIZE],
+                dirty: false,
+                last_used: clock,
+            });
+            inner.lines.len() - 1
+        } else {
+            let index = (0..inner.lines.len())
+                .min_by_key(|&index| inner.lines[index].last_used)
+                .unwrap();
+            self.write_back(&mut inner.lines[index])?;
+            index
+        };
+        let line = &mut inner.lines[index];
+        line.block_id = block_id;
+        line.last_used = clock;
+        if load {
+            if let Err(error) = self.device.read_at(block_id, &mut line.data) {
+                // 读取失败时不能保留这个块，否则之后会读到错误的数据
+                inner.lines.swap_remove(index);
+                return Err(error);
+            }
+        }
+        Ok(index)
+    }
+
+    /// 如果块是脏的，将其写回设备
+    fn write_back(&self, line: &mut CacheLine) -> dev::Result<()> {
+        if line.dirty {
+            self.device.write_at(line.block_id, &line.data)?;
+            line.dirty = false;
+        }
+        Ok(())
+    }
+
+    /// 将所有脏块写回设备
+    fn flush(&self, inner: &mut BlockCacheInner) -> dev::Result<()> {
+        for line in inner.lines.iter_mut() {
+            self.write_back(line)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: BlockDevice> BlockDevice for BlockCache<T> {
+    const BLOCK_SIZE_LOG2: u8 = T::BLOCK_SIZE_LOG2;
+
+    /// 读取某个块到 buf 中，未命中时从设备读入缓存
+    fn read_at(&self, block_id: usize, buf: &mut [u8]) -> dev::Result<()> {
+        let mut inner = self.inner.lock();
+        let index = self.get_line(&mut inner, block_id, true)?;
+        buf.copy_from_slice(&inner.lines[index].data);
+        Ok(())
+    }
+
+    /// 将 buf 中的数据写入缓存，写直达时同时写入设备
+    fn write_at(&self, block_id: usize, buf: &[u8]) -> dev::Result<()> {
+        let mut inner = self.inner.lock();
+        // 整块写入，不需要先读出原来的内容
+        let index = self.get_line(&mut inner, block_id, false)?;
+        let line = &mut inner.lines[index];
+        line.data.copy_from_slice(buf);
+        line.dirty = true;
+        if !BLOCK_CACHE_WRITE_BACK {
+            self.write_back(line)?;
+        }
+        Ok(())
+    }
+
+    /// 写回所有脏块，然后同步设备
+    fn sync(&self) -> dev::Result<()> {
+        self.flush(&mut self.inner.lock())?;
+        self.device.sync()
+    }
+}
+
+impl<T: BlockDevice> TryFlush for BlockCache<T> {
+    fn try_flush(&self) -> bool {
+        match self.inner.try_lock() {
+            Some(mut inner) => self.flush(&mut inner).is_ok() && self.device.sync().is_ok(),
+            None => false,
+        }
+    }
+}
+
+/// 尝试写回所有块缓存，用于 panic 后关机前
+///
+/// 只写回缓存中已有的数据，不会调用文件系统的 [`FileSystem::sync`]，
+/// 因为 panic 可能发生在文件系统持有锁的时候。返回是否全部写回成功。
+pub fn try_flush_block_caches() -> bool {
+    let caches = match BLOCK_CACHES.try_lock() {
+        Some(caches) => caches,
+        None => return false,
+    };
+    caches
+        .iter()
+        .filter_map(Weak::upgrade)
+        .fold(true, |success, cache| cache.try_flush() && success)
+}
@@ -1,4 +1,22 @@
 //! 文件系统的配置信息
 
+/// 路径的最大长度
+pub const PATH_MAX: usize = 4096;
+
 /// 块设备的 Cache 块个数
 pub const BLOCK_CACHE_CAPACITY: usize = 0x10;
+
+/// 块设备的 Cache 是否为写回式，为 `false` 时每次写入都会立即写入设备
+pub const BLOCK_CACHE_WRITE_BACK: bool = true;
+
+/// 写回线程每隔多少次时钟中断将所有文件系统写回设备
+pub const FLUSH_INTERVAL_TICKS: usize = 100;
+
+/// 页缓存未命中时，额外预读之后的页数
+pub const PAGE_CACHE_READ_AHEAD: usize = 4;
+
+/// 剩余物理页少于此数时，页缓存开始换出
+pub const PAGE_CACHE_LOW_WATERMARK: usize = 256;
+
+/// 页缓存换出时，换出到剩余物理页不少于此数为止
+pub const PAGE_CACHE_HIGH_WATERMARK: usize = 512;
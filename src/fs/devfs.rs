@@ -0,0 +1,350 @@
+//! 设备文件系统 [`DevFs`]
+//!
+//! 挂载在 `/dev`，包含以下设备节点：
+//! - `null`：读取得到文件结尾，写入的数据全部丢弃
+//! - `zero`：读取得到全零，写入的数据全部丢弃
+//! - `random`：读取得到伪随机数据
+//! - `console`：控制台，读写分别对应 [`STDIN`] 和 [`STDOUT`]
+//! - `vda`、`vdb` … `vdz`、`vdaa` …：[`static@DRIVERS`] 中的块设备，可以按字节读写整个磁盘
+//!
+//! 注意直接读写块设备会绕过文件系统的缓存，不应在设备已经挂载时写入。
+
+use super::*;
+use crate::drivers::driver::Driver;
+use alloc::{
+    string::{String, ToString},
+    sync::Weak,
+};
+use core::cmp::min;
+use spin::RwLock;
+
+/// 块设备的块大小
+const BLOCK_SIZE: usize = 512;
+
+/// 字符设备的名字
+const CHAR_DEVICES: [&str; 4] = ["null", "zero", "random", "console"];
+
+/// 设备文件系统
+///
+/// inode 持有文件系统的强引用，卸载之后已经打开的设备仍然可以使用；
+/// 为避免循环引用，文件系统不保存根目录，每次需要时创建
+pub struct DevFs {
+    /// 自身的引用，用于创建节点，在 [`DevFs::new`] 中设置
+    this: RwLock<Weak<DevFs>>,
+}
+
+/// 设备文件系统的根目录
+pub struct DevRoot {
+    /// 所属的文件系统
+    fs: Arc<DevFs>,
+}
+
+/// 设备的种类
+enum DeviceKind {
+    Null,
+    Zero,
+    Random,
+    Console,
+    /// 块设备，以及它是第几个块设备
+    Block(Arc<dyn Driver>, usize),
+}
+
+/// 一个设备节点
+pub struct DeviceINode {
+    /// 设备种类
+    kind: DeviceKind,
+    /// 所属的文件系统
+    fs: Arc<DevFs>,
+}
+
+impl DevFs {
+    /// 创建设备文件系统
+    pub fn new() -> Arc<Self> {
+        let fs = Arc::new(Self {
+            this: RwLock::new(Weak::new()),
+        });
+        *fs.this.write() = Arc::downgrade(&fs);
+        fs
+    }
+}
+
+impl FileSystem for DevFs {
+    fn sync(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn root_inode(&self) -> Arc<dyn INode> {
+        // 能够调用这个方法时文件系统一定还没有释放
+        let fs = self.this.read().upgrade().unwrap();
+        Arc::new(DevRoot { fs })
+    }
+
+    fn info(&self) -> FsInfo {
+        FsInfo {
+            bsize: 0,
+            frsize: 0,
+            blocks: 0,
+            bfree: 0,
+            bavail: 0,
+            files: 0,
+            ffree: 0,
+            namemax: 255,
+        }
+    }
+}
+
+impl DevRoot {
+    /// 所有设备的名字，字符设备在前，块设备在后
+    fn entries(&self) -> Vec<String> {
+        let mut entries: Vec<String> = CHAR_DEVICES.iter().map(|name| name.to_string()).collect();
+        let block_count = DRIVERS
+            .read()
+            .iter()
+            .filter(|driver| driver.device_type() == DeviceType::Block)
+            .count();
+        for index in 0..block_count {
+            entries.push(block_device_name(index));
+        }
+        entries
+    }
+}
+
+impl INode for DevRoot {
+    fn read_at(&self, _offset: usize, _buf: &mut [u8]) -> Result<usize> {
+        Err(FsError::IsDir)
+    }
+
+    fn write_at(&self, _offset: usize, _buf: &[u8]) -> Result<usize> {
+        Err(FsError::IsDir)
+    }
+
+    fn poll(&self) -> Result<PollStatus> {
+        Err(FsError::IsDir)
+    }
+
+    fn metadata(&self) -> Result<Metadata> {
+        Ok(Metadata {
+            dev: 0,
+            inode: 1,
+            size: 0,
+            blk_size: 0,
+            blocks: 0,
+            atime: Timespec { sec: 0, nsec: 0 },
+            mtime: Timespec { sec: 0, nsec: 0 },
+            ctime: Timespec { sec: 0, nsec: 0 },
+            type_: FileType::Dir,
+            mode: 0o755,
+            nlinks: 2,
+            uid: 0,
+            gid: 0,
+            rdev: 0,
+        })
+    }
+
+    fn find(&self, name: &str) -> Result<Arc<dyn INode>> {
+        let kind = match name {
+            "." | ".." => return Ok(self.fs.root_inode()),
+            "null" => DeviceKind::Null,
+            "zero" => DeviceKind::Zero,
+            "random" => DeviceKind::Random,
+            "console" => DeviceKind::Console,
+            name => {
+                let driver = mount::block_driver(name).map_err(|_| FsError::EntryNotFound)?;
+                let index = block_device_index(name).ok_or(FsError::EntryNotFound)?;
+                DeviceKind::Block(driver, index)
+            }
+        };
+        Ok(Arc::new(DeviceINode {
+            kind,
+            fs: self.fs.clone(),
+        }))
+    }
+
+    fn get_entry(&self, id: usize) -> Result<String> {
+        match id {
+            0 => Ok(".".to_string()),
+            1 => Ok("..".to_string()),
+            id => self
+                .entries()
+                .into_iter()
+                .nth(id - 2)
+                .ok_or(FsError::EntryNotFound),
+        }
+    }
+
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        self.fs.clone()
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl INode for DeviceINode {
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
+        match &self.kind {
+            DeviceKind::Null => Ok(0),
+            DeviceKind::Zero => {
+                for byte in buf.iter_mut() {
+                    *byte = 0;
+                }
+                Ok(buf.len())
+            }
+            DeviceKind::Random => {
+                fill_random(buf);
+                Ok(buf.len())
+            }
+            DeviceKind::Console => STDIN.read_at(0, buf),
+            DeviceKind::Block(driver, _) => {
+                let mut block = [0u8; BLOCK_SIZE];
+                let mut position = offset;
+                while position < offset + buf.len() {
+                    if !driver.read_block(position / BLOCK_SIZE, &mut block) {
+                        break;
+                    }
+                    let block_offset = position % BLOCK_SIZE;
+                    let length = min(BLOCK_SIZE - block_offset, offset + buf.len() - position);
+                    buf[position - offset..position - offset + length]
+                        .copy_from_slice(&block[block_offset..block_offset + length]);
+                    position += length;
+                }
+                if position == offset && !buf.is_empty() {
+                    Err(FsError::DeviceError)
+                } else {
+                    Ok(position - offset)
+                }
+            }
+        }
+    }
+
+    fn write_at(&self, offset: usize, buf: &[u8]) -> Result<usize> {
+        match &self.kind {
+            DeviceKind::Null | DeviceKind::Zero | DeviceKind::Random => Ok(buf.len()),
+            DeviceKind::Console => STDOUT.write_at(0, buf),
+            DeviceKind::Block(driver, _) => {
+                let mut block = [0u8; BLOCK_SIZE];
+                let mut position = offset;
+                while position < offset + buf.len() {
+                    let block_id = position / BLOCK_SIZE;
+                    let block_offset = position % BLOCK_SIZE;
+                    let length = min(BLOCK_SIZE - block_offset, offset + buf.len() - position);
+                    // 不是整块写入时，需要先读出原来的数据
+                    if length != BLOCK_SIZE && !driver.read_block(block_id, &mut block) {
+                        break;
+                    }
+                    block[block_offset..block_offset + length]
+                        .copy_from_slice(&buf[position - offset..position - offset + length]);
+                    if !driver.write_block(block_id, &block) {
+                        break;
+                    }
+                    position += length;
+                }
+                if position == offset && !buf.is_empty() {
+                    Err(FsError::DeviceError)
+                } else {
+                    Ok(position - offset)
+                }
+            }
+        }
+    }
+
+    fn poll(&self) -> Result<PollStatus> {
+        Ok(PollStatus {
+            read: true,
+            write: true,
+            error: false,
+        })
+    }
+
+    fn metadata(&self) -> Result<Metadata> {
+        // 设备号与 Linux 保持一致
+        let (type_, major, minor, inode) = match &self.kind {
+            DeviceKind::Null => (FileType::CharDevice, 1, 3, 2),
+            DeviceKind::Zero => (FileType::CharDevice, 1, 5, 3),
+            DeviceKind::Random => (FileType::CharDevice, 1, 8, 4),
+            DeviceKind::Console => (FileType::CharDevice, 5, 1, 5),
+            DeviceKind::Block(_, index) => (FileType::BlockDevice, 254, index * 16, 6 + index),
+        };
+        Ok(Metadata {
+            dev: 0,
+            inode,
+            size: 0,
+            blk_size: BLOCK_SIZE,
+            blocks: 0,
+            atime: Timespec { sec: 0, nsec: 0 },
+            mtime: Timespec { sec: 0, nsec: 0 },
+            ctime: Timespec { sec: 0, nsec: 0 },
+            type_,
+            mode: 0o666,
+            nlinks: 1,
+            uid: 0,
+            gid: 0,
+            rdev: (major << 8) | minor,
+        })
+    }
+
+    fn sync_all(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn sync_data(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        self.fs.clone()
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// 块设备的名字，按顺序为 `vda` … `vdz`、`vdaa` … `vdzz`、`vdaaa` …
+fn block_device_name(index: usize) -> String {
+    let mut suffix = Vec::new();
+    let mut n = index + 1;
+    while n > 0 {
+        n -= 1;
+        suffix.push(b'a' + (n % 26) as u8);
+        n /= 26;
+    }
+    let mut name = "vd".to_string();
+    name.extend(suffix.iter().rev().map(|&c| c as char));
+    name
+}
+
+/// 由块设备的名字得到它的序号，是 [`block_device_name`] 的逆运算
+pub(super) fn block_device_index(name: &str) -> Option<usize> {
+    let suffix = name.strip_prefix("vd")?;
+    if suffix.is_empty() {
+        return None;
+    }
+    let mut n = 0usize;
+    for c in suffix.bytes() {
+        if !c.is_ascii_lowercase() {
+            return None;
+        }
+        n = n.checked_mul(26)?.checked_add((c - b'a') as usize + 1)?;
+    }
+    Some(n - 1)
+}
+
+lazy_static! {
+    /// 伪随机数发生器的状态
+    static ref RANDOM_STATE: Mutex<u64> =
+        Mutex::new(riscv::register::time::read() as u64 | 1);
+}
+
+/// 用伪随机数据填充缓冲区（xorshift64*），不能用于密码学用途
+pub fn fill_random(buf: &mut [u8]) {
+    let mut state = RANDOM_STATE.lock();
+    for chunk in buf.chunks_mut(8) {
+        *state ^= *state >> 12;
+        *state ^= *state << 25;
+        *state ^= *state >> 27;
+        let value = state.wrapping_mul(0x2545_f491_4f6c_dd1d);
+        chunk.copy_from_slice(&value.to_le_bytes()[..chunk.len()]);
+    }
+}
@@ -0,0 +1,66 @@
+//! 打开的文件 [`FileHandle`]
+//!
+//! 进程的文件描述符对应一个 [`FileHandle`]，其中记录了读写的位置。
+//! 可以缓存的文件经过 [`static@PAGE_CACHE`] 读写，其他文件直接读写 [`INode`]。
+
+use super::*;
+
+/// 打开的文件
+pub struct FileHandle {
+    /// 对应的文件
+    pub inode: Arc<dyn INode>,
+    /// 当前读写的位置
+    offset: Mutex<usize>,
+    /// 是否支持读写位置，控制台等字符设备总是从 0 开始读写
+    seekable: bool,
+    /// 是否经过页缓存
+    cached: bool,
+}
+
+impl FileHandle {
+    /// 打开文件，读写位置从 0 开始
+    pub fn new(inode: Arc<dyn INode>) -> Arc<Self> {
+        // 控制台不支持 metadata，视作字符设备
+        let seekable = match inode.metadata() {
+            Ok(metadata) => matches!(metadata.type_, FileType::File | FileType::BlockDevice),
+            Err(_) => false,
+        };
+        let cached = is_cacheable(&inode);
+        Arc::new(Self {
+            inode,
+            offset: Mutex::new(0),
+            seekable,
+            cached,
+        })
+    }
+
+    /// 从当前位置读取，并将位置后移
+    pub fn read(&self, buf: &mut [u8]) -> Result<usize> {
+        if !self.seekable {
+            return self.inode.read_at(0, buf);
+        }
+        let mut offset = self.offset.lock();
+        let count = if self.cached {
+            PAGE_CACHE.read(&self.inode, *offset, buf)?
+        } else {
+            self.inode.read_at(*offset, buf)?
+        };
+        *offset += count;
+        Ok(count)
+    }
+
+    /// 从当前位置写入，并将位置后移
+    pub fn write(&self, buf: &[u8]) -> Result<usize> {
+        if !self.seekable {
+            return self.inode.write_at(0, buf);
+        }
+        let mut offset = self.offset.lock();
+        let count = if self.cached {
+            PAGE_CACHE.write(&self.inode, *offset, buf)?
+        } else {
+            self.inode.write_at(*offset, buf)?
+        };
+        *offset += count;
+        Ok(count)
+    }
+}
@@ -1,57 +1,109 @@
 //! 文件系统
 //!
-//! 将读取第一个块设备作为根文件系统
+//! 将读取第一个块设备作为根文件系统，其他文件系统可以通过 [`mount`] 挂载到目录上
 
 use crate::drivers::{
     block::BlockDevice,
     driver::{DeviceType, DRIVERS},
 };
+use crate::interrupt::ticks;
 use crate::kernel::Condvar;
+use crate::process::live_threads;
 use alloc::{sync::Arc, vec::Vec};
 use core::any::Any;
 use lazy_static::lazy_static;
 use rcore_fs_sfs::SimpleFileSystem;
 use spin::Mutex;
 
+mod block_cache;
 mod config;
+mod devfs;
+mod file;
 mod inode_ext;
+mod mount;
+mod page_cache;
+mod procfs;
 mod stdin;
 mod stdout;
+mod tmpfs;
 
+pub use block_cache::{try_flush_block_caches, BlockCache};
 pub use config::*;
+pub use devfs::{fill_random, DevFs};
+pub use file::FileHandle;
 pub use inode_ext::INodeExt;
-pub use rcore_fs::{dev::block_cache::BlockCache, vfs::*};
+pub use mount::{lookup, mount, umount, MOUNT_TABLE};
+pub use page_cache::{is_cacheable, DenyWrite, PageCache, PAGE_CACHE};
+pub use procfs::ProcFs;
+pub use rcore_fs::vfs::*;
 pub use stdin::STDIN;
 pub use stdout::STDOUT;
+pub use tmpfs::TmpFs;
 
 // BlockCache
-// 该模块也是 rcore-fs 提供的
 // 提供了一个存储设备在内存 Cache 的抽象，通过调用 BlockCache::new(device, BLOCK_CACHE_CAPACITY) 就可以把 device 自动变为一个有 Cache 的设备。
+// 写入的数据会留在 Cache 中，直到被换出或调用 sync 时才写回设备。
 // 根目录将会在我们第一次使用 ROOT_INODE 时进行初始化，而初始化的方式是找到全部设备驱动中的第一个存储设备作为根目录。
 
 lazy_static! {
     /// 根文件系统的根目录的 INode, INode由rcore_fs提供
-    pub static ref ROOT_INODE: Arc<dyn INode> = {
-        // 选择第一个块设备
-        for driver in DRIVERS.read().iter() {
-            if driver.device_type() == DeviceType::Block {
-                let device = BlockDevice(driver.clone());
-                // 动态分配一段内存空间作为设备 Cache
-                let device_with_cache = Arc::new(BlockCache::new(device, BLOCK_CACHE_CAPACITY));
-                // 最后我们用 SimpleFileSystem::open 打开并返回根节点即可。
-                return SimpleFileSystem::open(device_with_cache)
-                    .expect("failed to open SFS")
-                    .root_inode();
-            }
-        }
-        panic!("failed to load fs")
-    };
+    ///
+    /// 不会跨越挂载点，按路径查找时应使用 [`lookup`]
+    pub static ref ROOT_INODE: Arc<dyn INode> = MOUNT_TABLE.read().root_inode();
 }
 
-/// 触发 [`static@ROOT_INODE`] 的初始化并打印根目录内容
+/// 触发 [`static@ROOT_INODE`] 的初始化，挂载内存、设备和进程信息文件系统并打印根目录内容
 pub fn init() {
     ROOT_INODE.ls();
+    mount_at("tmpfs", "/tmp", "tmpfs");
+    mount_at("devfs", "/dev", "devfs");
+    mount_at("procfs", "/proc", "procfs");
     println!("mod fs initialized");
 }
 
+/// 在根文件系统中确保挂载点目录存在，然后挂载文件系统
+fn mount_at(source: &str, target: &str, fstype: &str) {
+    let name = target.trim_start_matches('/');
+    if ROOT_INODE.find(name).is_err() {
+        ROOT_INODE
+            .create(name, FileType::Dir, 0o755)
+            .expect("failed to create mount point");
+    }
+    mount(source, target, fstype).expect("failed to mount");
+}
+
+/// 将所有挂载的文件系统写回设备
+pub fn sync_all() -> Result<()> {
+    let filesystems: Vec<Arc<dyn FileSystem>> =
+        MOUNT_TABLE.read().iter().map(|(_, fs)| fs.clone()).collect();
+    for fs in filesystems {
+        fs.sync()?;
+    }
+    Ok(())
+}
 
+/// 写回线程，每隔 [`FLUSH_INTERVAL_TICKS`] 次时钟中断执行一次 [`sync_all`]
+///
+/// 当其他线程全部结束时，最后写回一次然后退出，使得系统可以正常关机
+pub fn flusher() {
+    use riscv::register::sstatus;
+    let mut last_flush = ticks();
+    loop {
+        // 内核线程可以被时钟中断打断，若在持有进程、文件系统或设备的锁时被切换，
+        // 中断处理或其他线程的系统调用就会在同一把锁上永远自旋，所以这里关闭中断
+        unsafe { sstatus::clear_sie() };
+        // 除自身以外是否还有其他线程
+        let others_alive = live_threads().len() > 1;
+        if !others_alive || ticks() - last_flush >= FLUSH_INTERVAL_TICKS {
+            last_flush = ticks();
+            if sync_all().is_err() {
+                println!("flusher: failed to sync filesystems");
+            }
+        }
+        unsafe { sstatus::set_sie() };
+        if !others_alive {
+            break;
+        }
+        unsafe { llvm_asm!("wfi" :::: "volatile") };
+    }
+}
@@ -0,0 +1,188 @@
+//! 挂载表 [`MountTable`]
+//!
+//! 每个挂载点是一个规范化后的绝对路径，对应一个文件系统。
+//! 解析路径时，选择与路径匹配的最长挂载点，然后在该文件系统内继续查找剩余部分，
+//! 这样路径解析就可以跨越挂载点。
+
+use super::*;
+use crate::drivers::driver::Driver;
+use alloc::{
+    collections::BTreeMap,
+    string::{String, ToString},
+};
+use spin::RwLock;
+
+lazy_static! {
+    /// 全局挂载表，初始化时将第一个块设备上的 SFS 挂载在根目录
+    pub static ref MOUNT_TABLE: RwLock<MountTable> = RwLock::new(MountTable::new(
+        open_filesystem("sfs", "vda").expect("failed to load fs")
+    ));
+}
+
+/// 挂载表
+pub struct MountTable {
+    /// 挂载点路径 -> 文件系统
+    mounts: BTreeMap<String, Arc<dyn FileSystem>>,
+}
+
+impl MountTable {
+    /// 以给定的文件系统作为根目录创建挂载表
+    pub fn new(root: Arc<dyn FileSystem>) -> Self {
+        let mut mounts = BTreeMap::new();
+        mounts.insert("/".to_string(), root);
+        Self { mounts }
+    }
+
+    /// 根文件系统的根目录
+    pub fn root_inode(&self) -> Arc<dyn INode> {
+        self.mounts["/"].root_inode()
+    }
+
+    /// 将文件系统挂载到 `target` 目录
+    ///
+    /// `target` 必须是已经存在的目录，且没有挂载其他文件系统
+    pub fn mount(&mut self, target: &str, fs: Arc<dyn FileSystem>) -> Result<()> {
+        let target = normalize(target);
+        if self.mounts.contains_key(&target) {
+            return Err(FsError::Busy);
+        }
+        if self.lookup(&target)?.metadata()?.type_ != FileType::Dir {
+            return Err(FsError::NotDir);
+        }
+        self.mounts.insert(target, fs);
+        Ok(())
+    }
+
+    /// 卸载 `target` 上的文件系统
+    ///
+    /// 根目录不能卸载；若其下还挂载有其他文件系统，返回 [`FsError::Busy`]
+    pub fn umount(&mut self, target: &str) -> Result<Arc<dyn FileSystem>> {
+        let target = normalize(target);
+        if target == "/" {
+            return Err(FsError::Busy);
+        }
+        if !self.mounts.contains_key(&target) {
+            return Err(FsError::InvalidParam);
+        }
+        if self
+            .mounts
+            .keys()
+            .any(|path| path != &target && is_prefix(&target, path))
+        {
+            return Err(FsError::Busy);
+        }
+        let fs = self.mounts.remove(&target).unwrap();
+        fs.sync()?;
+        PAGE_CACHE.invalidate_fs(&fs);
+        super::tmpfs::detach(&fs);
+        Ok(fs)
+    }
+
+    /// 按绝对路径查找 [`INode`]，会跨越挂载点
+    pub fn lookup(&self, path: &str) -> Result<Arc<dyn INode>> {
+        let path = normalize(path);
+        // 找到最长的、作为路径前缀的挂载点
+        let (mount_point, fs) = self
+            .mounts
+            .iter()
+            .filter(|(mount_point, _)| is_prefix(mount_point, &path))
+            .max_by_key(|(mount_point, _)| mount_point.len())
+            .unwrap();
+        let rest = path[mount_point.len()..].trim_start_matches('/');
+        let root = fs.root_inode();
+        if rest.is_empty() {
+            Ok(root)
+        } else {
+            root.lookup(rest)
+        }
+    }
+
+    /// 遍历所有挂载点
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Arc<dyn FileSystem>)> {
+        self.mounts.iter()
+    }
+}
+
+/// 按路径查找 [`INode`]，会跨越挂载点
+///
+/// 相对路径视作相对根目录
+pub fn lookup(path: &str) -> Result<Arc<dyn INode>> {
+    MOUNT_TABLE.read().lookup(path)
+}
+
+/// 将 `source` 上类型为 `fstype` 的文件系统挂载到 `target`
+pub fn mount(source: &str, target: &str, fstype: &str) -> Result<()> {
+    let fs = open_filesystem(fstype, source)?;
+    MOUNT_TABLE.write().mount(target, fs)
+}
+
+/// 卸载 `target` 上的文件系统
+pub fn umount(target: &str) -> Result<()> {
+    MOUNT_TABLE.write().umount(target).map(|_| ())
+}
+
+/// 按类型打开一个文件系统
+///
+/// - `sfs`：`source` 为块设备名，如 `vda` 或 `/dev/vda`
+/// - `tmpfs`：内存文件系统，忽略 `source`
+/// - `devfs`：设备文件系统，忽略 `source`
+/// - `procfs`：进程信息文件系统，忽略 `source`
+pub fn open_filesystem(fstype: &str, source: &str) -> Result<Arc<dyn FileSystem>> {
+    match fstype {
+        "sfs" => {
+            let device = BlockDevice(block_driver(source)?);
+            // 动态分配一段内存空间作为设备 Cache
+            let device_with_cache = BlockCache::new(device, BLOCK_CACHE_CAPACITY);
+            Ok(SimpleFileSystem::open(device_with_cache)?)
+        }
+        "tmpfs" => Ok(TmpFs::new()),
+        "devfs" => Ok(DevFs::new()),
+        "procfs" => Ok(ProcFs::new()),
+        _ => Err(FsError::WrongFs),
+    }
+}
+
+/// 按名字找到块设备驱动
+///
+/// 块设备按照在 [`static@DRIVERS`] 中出现的顺序依次命名为 `vda` … `vdz`、`vdaa` …
+pub fn block_driver(name: &str) -> Result<Arc<dyn Driver>> {
+    let name = name.trim_start_matches("/dev/");
+    let index = super::devfs::block_device_index(name).ok_or(FsError::NoDevice)?;
+    DRIVERS
+        .read()
+        .iter()
+        .filter(|driver| driver.device_type() == DeviceType::Block)
+        .nth(index)
+        .cloned()
+        .ok_or(FsError::NoDevice)
+}
+
+/// 将路径规范化为不含 `.`、`..` 和重复 `/` 的绝对路径
+fn normalize(path: &str) -> String {
+    let mut components: Vec<&str> = Vec::new();
+    for component in path.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => {
+                components.pop();
+            }
+            name => components.push(name),
+        }
+    }
+    let mut result = String::new();
+    for component in components {
+        result.push('/');
+        result.push_str(component);
+    }
+    if result.is_empty() {
+        result.push('/');
+    }
+    result
+}
+
+/// `prefix` 是否为路径 `path` 的前缀（按路径分量比较）
+fn is_prefix(prefix: &str, path: &str) -> bool {
+    prefix == "/"
+        || path == prefix
+        || (path.starts_with(prefix) && path.as_bytes()[prefix.len()] == b'/')
+}
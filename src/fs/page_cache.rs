@@ -0,0 +1,362 @@
+//! 页缓存 [`PageCache`]
+//!
+//! 以页为单位缓存块设备上文件系统中的文件内容，键为（文件系统、inode 编号、页号）。
+//! 缓存页就是 [`FrameTracker`]，可以直接映射到进程的地址空间中，
+//! 这样读写文件和文件映射看到的是同一份数据。
+//!
+//! - 读取未缓存的页时，会顺带预读之后的 [`PAGE_CACHE_READ_AHEAD`] 页
+//! - 写入采用写直达：先写入文件，再更新已经缓存的页
+//! - 剩余物理页少于 [`PAGE_CACHE_LOW_WATERMARK`] 时，换出最久未使用且没有被映射的页，
+//!   直到剩余物理页不少于 [`PAGE_CACHE_HIGH_WATERMARK`]
+//! - 只在查找和修改索引时持有整个缓存的锁，读写文件时不持有，一次慢的读写不会阻塞其他文件的访问。
+//!   正在读入的页先以 [`PageState::Loading`] 放入缓存，访问同一页的其他线程等待读入完成
+//! - 程序的代码等只读段直接映射缓存页，映射期间持有 [`DenyWrite`]，文件不能被写入
+
+use super::*;
+use crate::memory::{frame::FrameTracker, FRAME_ALLOCATOR, PAGE_SIZE};
+use crate::process::Lock;
+use alloc::collections::BTreeMap;
+use core::cmp::min;
+use core::sync::atomic::spin_loop_hint;
+use rcore_fs_sfs::INodeImpl;
+
+lazy_static! {
+    /// 全局的页缓存
+    pub static ref PAGE_CACHE: PageCache = PageCache {
+        inner: Mutex::new(PageCacheInner::default()),
+    };
+
+    /// 每个文件被 [`DenyWrite`] 拒绝写入的次数
+    ///
+    /// 进程可能在中断处理中被释放，所以使用关闭中断的 [`Lock`]
+    static ref WRITE_DENIED: Lock<BTreeMap<FileKey, usize>> = Lock::new(BTreeMap::new());
+}
+
+/// 文件的标识：（文件系统的地址，inode 编号）
+type FileKey = (usize, usize);
+
+/// 缓存页的状态
+enum PageState {
+    /// 正在由某个线程从文件读入
+    Loading,
+    /// 已经读入，页被映射时引用计数大于 1
+    Ready(Arc<FrameTracker>),
+    /// 读入失败，已经从缓存中移除，等待的线程需要重新读入
+    Failed,
+}
+
+/// 一个缓存页
+struct CachedPage {
+    /// 页的状态，单独上锁，读入时不需要持有整个缓存的锁
+    state: Arc<Mutex<PageState>>,
+    /// 最近一次使用的时间，用于选择换出的页
+    last_used: usize,
+}
+
+/// 页缓存
+pub struct PageCache {
+    /// 缓存页的索引，读写文件时不持有
+    inner: Mutex<PageCacheInner>,
+}
+
+/// [`PageCache`] 中需要上锁的部分
+#[derive(Default)]
+struct PageCacheInner {
+    /// （文件，页号）-> 缓存页
+    pages: BTreeMap<(FileKey, usize), CachedPage>,
+    /// 每次访问时递增的计数
+    clock: usize,
+}
+
+impl PageCache {
+    /// 取得文件的第 `index` 页，未缓存时从文件读入
+    ///
+    /// 超出文件结尾的部分为 0。返回的页可以映射到进程的地址空间中
+    pub fn get_page(&self, inode: &Arc<dyn INode>, index: usize) -> Result<Arc<FrameTracker>> {
+        let file = file_key(inode)?;
+        let size = inode.metadata()?.size;
+        self.page(inode, file, index, size)
+    }
+
+    /// 经过页缓存从文件的 `offset` 处读取
+    pub fn read(&self, inode: &Arc<dyn INode>, offset: usize, buf: &mut [u8]) -> Result<usize> {
+        let file = file_key(inode)?;
+        let size = inode.metadata()?.size;
+        if offset >= size {
+            return Ok(0);
+        }
+        let end = min(offset + buf.len(), size);
+        let mut position = offset;
+        while position < end {
+            let page_offset = position % PAGE_SIZE;
+            let length = min(PAGE_SIZE - page_offset, end - position);
+            let frame = self.page(inode, file, position / PAGE_SIZE, size)?;
+            buf[position - offset..position - offset + length]
+                .copy_from_slice(&frame[page_offset..page_offset + length]);
+            position += length;
+        }
+        Ok(end - offset)
+    }
+
+    /// 写入文件的 `offset` 处，并更新已经缓存的页
+    ///
+    /// 正在读入的页等读入完成之后再更新，这样它不会保留写入之前的数据
+    pub fn write(&self, inode: &Arc<dyn INode>, offset: usize, buf: &[u8]) -> Result<usize> {
+        let file = file_key(inode)?;
+        let written = inode.write_at(offset, buf)?;
+        let mut position = offset;
+        while position < offset + written {
+            let page_offset = position % PAGE_SIZE;
+            let length = min(PAGE_SIZE - page_offset, offset + written - position);
+            if let Some(frame) = self.cached_frame(file, position / PAGE_SIZE) {
+                // 缓存页可能同时被映射，直接通过线性映射修改
+                let data = frame.page_number().deref_kernel();
+                data[page_offset..page_offset + length]
+                    .copy_from_slice(&buf[position - offset..position - offset + length]);
+            }
+            position += length;
+        }
+        Ok(written)
+    }
+
+    /// 将缓存页写回文件，用于共享映射的页面被修改之后
+    ///
+    /// 只写回文件结尾之前的部分，未缓存的页不需要写回
+    pub fn write_back(&self, inode: &Arc<dyn INode>, index: usize) -> Result<()> {
+        let file = file_key(inode)?;
+        if let Some(frame) = self.cached_frame(file, index) {
+            let size = inode.metadata()?.size;
+            let start = index * PAGE_SIZE;
+            if start < size {
+                let length = min(PAGE_SIZE, size - start);
+                inode.write_at(start, &frame[..length])?;
+            }
+        }
+        Ok(())
+    }
+
+    /// 丢弃某个文件系统的所有缓存页，在卸载时调用
+    ///
+    /// 已经被映射的页不会被释放，但之后不会再通过缓存找到它们
+    pub fn invalidate_fs(&self, fs: &Arc<dyn FileSystem>) {
+        let fs = Arc::as_ptr(fs) as *const () as usize;
+        self.inner
+            .lock()
+            .pages
+            .retain(|((page_fs, _), _), _| *page_fs != fs);
+    }
+
+    /// 换出最久未使用且没有被映射的页，直到剩余物理页不少于 [`PAGE_CACHE_HIGH_WATERMARK`]
+    ///
+    /// 返回换出的页数
+    pub fn reclaim(&self) -> usize {
+        let mut reclaimed = 0;
+        let mut inner = self.inner.lock();
+        while FRAME_ALLOCATOR.lock().free_frames() < PAGE_CACHE_HIGH_WATERMARK {
+            let victim = inner
+                .pages
+                .iter()
+                .filter(|(_, page)| match &*page.state.lock() {
+                    PageState::Ready(frame) => Arc::strong_count(frame) == 1,
+                    _ => false,
+                })
+                .min_by_key(|(_, page)| page.last_used)
+                .map(|(key, _)| *key);
+            match victim {
+                Some(key) => {
+                    inner.pages.remove(&key);
+                    reclaimed += 1;
+                }
+                None => break,
+            }
+        }
+        reclaimed
+    }
+
+    /// 文件是否被 [`DenyWrite`] 拒绝写入
+    pub fn write_denied(&self, inode: &Arc<dyn INode>) -> bool {
+        match file_key(inode) {
+            Ok(file) => WRITE_DENIED.lock().contains_key(&file),
+            Err(_) => false,
+        }
+    }
+
+    /// 缓存的页数
+    pub fn cached_pages(&self) -> usize {
+        self.inner.lock().pages.len()
+    }
+
+    /// 取得一页，未缓存时读入该页并预读之后的页
+    fn page(
+        &self,
+        inode: &Arc<dyn INode>,
+        file: FileKey,
+        index: usize,
+        size: usize,
+    ) -> Result<Arc<FrameTracker>> {
+        let (frame, loaded) = self.load(inode, file, index, size)?;
+        if loaded {
+            for next in index + 1..=index + PAGE_CACHE_READ_AHEAD {
+                if next * PAGE_SIZE >= size || self.load(inode, file, next, size).is_err() {
+                    break;
+                }
+            }
+        }
+        Ok(frame)
+    }
+
+    /// 取得一页，未缓存时从文件读入并加入缓存，同时返回是否从文件读入
+    ///
+    /// 读入之前先放入 [`PageState::Loading`] 的页，然后释放整个缓存的锁
+    fn load(
+        &self,
+        inode: &Arc<dyn INode>,
+        file: FileKey,
+        index: usize,
+        size: usize,
+    ) -> Result<(Arc<FrameTracker>, bool)> {
+        let state = loop {
+            let state = {
+                let mut inner = self.inner.lock();
+                inner.clock += 1;
+                let clock = inner.clock;
+                match inner.pages.get_mut(&(file, index)) {
+                    Some(page) => {
+                        page.last_used = clock;
+                        page.state.clone()
+                    }
+                    None => {
+                        let state = Arc::new(Mutex::new(PageState::Loading));
+                        let page = CachedPage {
+                            state: state.clone(),
+                            last_used: clock,
+                        };
+                        inner.pages.insert((file, index), page);
+                        break state;
+                    }
+                }
+            };
+            // 其他线程读入失败时，由当前线程重新读入
+            if let Some(frame) = Self::wait(&state) {
+                return Ok((frame, false));
+            }
+        };
+        match self.read_page(inode, index, size) {
+            Ok(frame) => {
+                let frame = Arc::new(frame);
+                *state.lock() = PageState::Ready(frame.clone());
+                Ok((frame, true))
+            }
+            Err(error) => {
+                {
+                    let mut inner = self.inner.lock();
+                    let current = inner.pages.get(&(file, index));
+                    if current.map_or(false, |page| Arc::ptr_eq(&page.state, &state)) {
+                        inner.pages.remove(&(file, index));
+                    }
+                }
+                *state.lock() = PageState::Failed;
+                Err(error)
+            }
+        }
+    }
+
+    /// 从文件读入一页，超出文件结尾的部分为 0
+    fn read_page(&self, inode: &Arc<dyn INode>, index: usize, size: usize) -> Result<FrameTracker> {
+        if FRAME_ALLOCATOR.lock().free_frames() < PAGE_CACHE_LOW_WATERMARK {
+            self.reclaim();
+        }
+        let mut frame = FRAME_ALLOCATOR
+            .lock()
+            .alloc()
+            .map_err(|_| FsError::NoDeviceSpace)?;
+        for byte in frame.iter_mut() {
+            *byte = 0;
+        }
+        let start = index * PAGE_SIZE;
+        if start < size {
+            let length = min(PAGE_SIZE, size - start);
+            inode.read_at(start, &mut frame[..length])?;
+        }
+        Ok(frame)
+    }
+
+    /// 已经缓存的页，正在读入时等待读入完成
+    fn cached_frame(&self, file: FileKey, index: usize) -> Option<Arc<FrameTracker>> {
+        let state = self.inner.lock().pages.get(&(file, index))?.state.clone();
+        Self::wait(&state)
+    }
+
+    /// 等待页读入完成，读入失败时返回 `None`
+    fn wait(state: &Mutex<PageState>) -> Option<Arc<FrameTracker>> {
+        loop {
+            match &*state.lock() {
+                PageState::Loading => {}
+                PageState::Ready(frame) => return Some(frame.clone()),
+                PageState::Failed => return None,
+            }
+            spin_loop_hint();
+        }
+    }
+}
+
+/// 拒绝写入一个文件，存在期间 [`PageCache::write_denied`] 返回 `true`
+///
+/// 缓存页被映射为程序的代码时，写入文件会改变正在运行的程序，所以映射期间持有，
+/// 与 Linux 中正在运行的程序返回 `ETXTBSY` 相同。不经过页缓存的文件不受影响
+pub struct DenyWrite {
+    file: Option<FileKey>,
+}
+
+impl DenyWrite {
+    /// 开始拒绝写入 `inode`
+    pub fn new(inode: &Arc<dyn INode>) -> Self {
+        let file = file_key(inode).ok();
+        Self::deny(file)
+    }
+
+    /// 增加文件被拒绝写入的次数
+    fn deny(file: Option<FileKey>) -> Self {
+        if let Some(file) = file {
+            *WRITE_DENIED.lock().entry(file).or_insert(0) += 1;
+        }
+        Self { file }
+    }
+}
+
+impl Clone for DenyWrite {
+    fn clone(&self) -> Self {
+        Self::deny(self.file)
+    }
+}
+
+impl Drop for DenyWrite {
+    fn drop(&mut self) {
+        if let Some(file) = self.file {
+            let mut denied = WRITE_DENIED.lock();
+            if let Some(count) = denied.get_mut(&file) {
+                *count -= 1;
+                if *count == 0 {
+                    denied.remove(&file);
+                }
+            }
+        }
+    }
+}
+
+/// 文件是否经过页缓存
+///
+/// 只缓存块设备上的 SFS 文件：内存文件系统的数据本来就在物理页中，
+/// 设备文件和进程信息文件的内容则不能被缓存
+pub fn is_cacheable(inode: &Arc<dyn INode>) -> bool {
+    inode.downcast_ref::<INodeImpl>().is_some()
+}
+
+/// 计算文件的标识
+fn file_key(inode: &Arc<dyn INode>) -> Result<FileKey> {
+    if !is_cacheable(inode) {
+        return Err(FsError::NotSupported);
+    }
+    let fs = Arc::as_ptr(&inode.fs()) as *const () as usize;
+    Ok((fs, inode.metadata()?.inode))
+}
@@ -0,0 +1,341 @@
+//! 进程信息文件系统 [`ProcFs`]
+//!
+//! 挂载在 `/proc`，所有文件的内容都在读取时生成：
+//! - `/proc/<pid>/status`：进程的基本信息，以及其中每个线程的调度状态
+//! - `/proc/<pid>/maps`：进程 [`MemorySet`] 中的所有映射片段
+//! - `/proc/meminfo`：物理页帧、页缓存、页表和内核堆的使用情况
+//! - `/proc/uptime`：根据时钟中断次数估算的运行时间，单位为秒
+//! - `/proc/interrupts`：各类中断发生的次数
+
+use super::*;
+use crate::interrupt::{uptime_centisecs, INTERRUPT_COUNTS, INTERRUPT_NAMES};
+use crate::memory::{
+    heap, mapping::PAGE_TABLE_FRAMES, Flags, MapType, MemorySet, FRAME_ALLOCATOR, PAGE_SIZE,
+};
+use crate::process::{Process, ProcessID};
+use alloc::{
+    format,
+    string::{String, ToString},
+    sync::Weak,
+};
+use core::cmp::min;
+use core::fmt::Write;
+use core::sync::atomic::Ordering;
+use spin::RwLock;
+
+/// 根目录下固定存在的文件
+const ROOT_FILES: [&str; 3] = ["meminfo", "uptime", "interrupts"];
+
+/// 进程目录下的文件
+const PROCESS_FILES: [&str; 2] = ["status", "maps"];
+
+/// 进程信息文件系统
+///
+/// 节点持有文件系统的强引用，卸载之后已经打开的文件仍然可以使用
+pub struct ProcFs {
+    /// 自身的引用，用于创建节点，在 [`ProcFs::new`] 中设置
+    this: RwLock<Weak<ProcFs>>,
+}
+
+/// 节点对应的内容
+#[derive(Clone, Copy)]
+enum ProcEntry {
+    Root,
+    MemInfo,
+    Uptime,
+    Interrupts,
+    Process(ProcessID),
+    Status(ProcessID),
+    Maps(ProcessID),
+}
+
+/// 一个节点，内容在读取时生成
+pub struct ProcINode {
+    /// 节点对应的内容
+    entry: ProcEntry,
+    /// 所属的文件系统
+    fs: Arc<ProcFs>,
+}
+
+impl ProcFs {
+    /// 创建进程信息文件系统
+    pub fn new() -> Arc<Self> {
+        let fs = Arc::new(Self {
+            this: RwLock::new(Weak::new()),
+        });
+        *fs.this.write() = Arc::downgrade(&fs);
+        fs
+    }
+
+    /// 创建一个节点
+    fn node(&self, entry: ProcEntry) -> Arc<ProcINode> {
+        // 能够调用这个方法时文件系统一定还没有释放
+        let fs = self.this.read().upgrade().unwrap();
+        Arc::new(ProcINode { entry, fs })
+    }
+}
+
+impl FileSystem for ProcFs {
+    fn sync(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn root_inode(&self) -> Arc<dyn INode> {
+        self.node(ProcEntry::Root)
+    }
+
+    fn info(&self) -> FsInfo {
+        FsInfo {
+            bsize: 0,
+            frsize: 0,
+            blocks: 0,
+            bfree: 0,
+            bavail: 0,
+            files: 0,
+            ffree: 0,
+            namemax: 255,
+        }
+    }
+}
+
+impl ProcINode {
+    /// 是否为目录
+    fn is_dir(&self) -> bool {
+        matches!(self.entry, ProcEntry::Root | ProcEntry::Process(_))
+    }
+
+    /// 目录中除 `.` 和 `..` 以外的内容
+    fn entries(&self) -> Result<Vec<String>> {
+        match self.entry {
+            ProcEntry::Root => {
+                let mut entries: Vec<String> =
+                    ROOT_FILES.iter().map(|name| name.to_string()).collect();
+                for process in Process::all() {
+                    entries.push(process.pid.to_string());
+                }
+                Ok(entries)
+            }
+            ProcEntry::Process(_) => Ok(PROCESS_FILES.iter().map(|name| name.to_string()).collect()),
+            _ => Err(FsError::NotDir),
+        }
+    }
+
+    /// 生成文件的内容
+    fn content(&self) -> Result<String> {
+        match self.entry {
+            ProcEntry::MemInfo => Ok(meminfo()),
+            ProcEntry::Uptime => {
+                let centisecs = uptime_centisecs();
+                Ok(format!("{}.{:02}\n", centisecs / 100, centisecs % 100))
+            }
+            ProcEntry::Interrupts => {
+                let mut content = String::new();
+                for (name, count) in INTERRUPT_NAMES.iter().zip(INTERRUPT_COUNTS.iter()) {
+                    writeln!(content, "{}:\t{}", name, count.load(Ordering::Relaxed)).unwrap();
+                }
+                Ok(content)
+            }
+            ProcEntry::Status(pid) => Ok(status(&get_process(pid)?)),
+            ProcEntry::Maps(pid) => Ok(maps(&get_process(pid)?.inner().memory_set)),
+            ProcEntry::Root | ProcEntry::Process(_) => Err(FsError::IsDir),
+        }
+    }
+
+    /// inode 编号，进程相关的节点按进程 ID 编号
+    fn inode_id(&self) -> usize {
+        match self.entry {
+            ProcEntry::Root => 1,
+            ProcEntry::MemInfo => 2,
+            ProcEntry::Uptime => 3,
+            ProcEntry::Interrupts => 4,
+            ProcEntry::Process(pid) => 0x100 + pid * 4,
+            ProcEntry::Status(pid) => 0x100 + pid * 4 + 1,
+            ProcEntry::Maps(pid) => 0x100 + pid * 4 + 2,
+        }
+    }
+}
+
+impl INode for ProcINode {
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
+        let content = self.content()?;
+        let content = content.as_bytes();
+        if offset >= content.len() {
+            return Ok(0);
+        }
+        let length = min(buf.len(), content.len() - offset);
+        buf[..length].copy_from_slice(&content[offset..offset + length]);
+        Ok(length)
+    }
+
+    fn write_at(&self, _offset: usize, _buf: &[u8]) -> Result<usize> {
+        Err(FsError::NotSupported)
+    }
+
+    fn poll(&self) -> Result<PollStatus> {
+        Ok(PollStatus {
+            read: true,
+            write: false,
+            error: false,
+        })
+    }
+
+    fn metadata(&self) -> Result<Metadata> {
+        let (type_, mode, nlinks) = if self.is_dir() {
+            (FileType::Dir, 0o555, 2)
+        } else {
+            (FileType::File, 0o444, 1)
+        };
+        Ok(Metadata {
+            dev: 0,
+            inode: self.inode_id(),
+            size: 0,
+            blk_size: 0,
+            blocks: 0,
+            atime: Timespec { sec: 0, nsec: 0 },
+            mtime: Timespec { sec: 0, nsec: 0 },
+            ctime: Timespec { sec: 0, nsec: 0 },
+            type_,
+            mode,
+            nlinks,
+            uid: 0,
+            gid: 0,
+            rdev: 0,
+        })
+    }
+
+    fn find(&self, name: &str) -> Result<Arc<dyn INode>> {
+        let entry = match (self.entry, name) {
+            (ProcEntry::Root, ".") | (ProcEntry::Root, "..") | (ProcEntry::Process(_), "..") => {
+                ProcEntry::Root
+            }
+            (ProcEntry::Process(pid), ".") => ProcEntry::Process(pid),
+            (ProcEntry::Root, "meminfo") => ProcEntry::MemInfo,
+            (ProcEntry::Root, "uptime") => ProcEntry::Uptime,
+            (ProcEntry::Root, "interrupts") => ProcEntry::Interrupts,
+            (ProcEntry::Root, name) => {
+                let pid = name.parse().map_err(|_| FsError::EntryNotFound)?;
+                get_process(pid)?;
+                ProcEntry::Process(pid)
+            }
+            (ProcEntry::Process(pid), "status") => ProcEntry::Status(pid),
+            (ProcEntry::Process(pid), "maps") => ProcEntry::Maps(pid),
+            (ProcEntry::Process(_), _) => return Err(FsError::EntryNotFound),
+            _ => return Err(FsError::NotDir),
+        };
+        Ok(self.fs.node(entry))
+    }
+
+    fn get_entry(&self, id: usize) -> Result<String> {
+        match id {
+            0 => Ok(".".to_string()),
+            1 => Ok("..".to_string()),
+            id => self
+                .entries()?
+                .into_iter()
+                .nth(id - 2)
+                .ok_or(FsError::EntryNotFound),
+        }
+    }
+
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        self.fs.clone()
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// 找到存活的进程，进程已经结束时返回 [`FsError::EntryNotFound`]
+fn get_process(pid: ProcessID) -> Result<Arc<Process>> {
+    Process::get(pid).ok_or(FsError::EntryNotFound)
+}
+
+/// 生成 `/proc/<pid>/status`
+fn status(process: &Process) -> String {
+    let (descriptors, vm_size) = {
+        let inner = process.inner();
+        let vm_size: usize = inner
+            .memory_set
+            .segments
+            .values()
+            .filter(|segment| segment.map_type != MapType::Linear)
+            .map(|segment| segment.page_range().len() * PAGE_SIZE)
+            .sum();
+        (inner.descriptors.iter().filter(|file| file.is_some()).count(), vm_size)
+    };
+    let threads = process.threads();
+
+    let mut content = String::new();
+    writeln!(content, "Pid:\t{}", process.pid).unwrap();
+    writeln!(content, "Kind:\t{}", if process.is_user { "user" } else { "kernel" }).unwrap();
+    writeln!(content, "Threads:\t{}", threads.len()).unwrap();
+    writeln!(content, "FDs:\t{}", descriptors).unwrap();
+    writeln!(content, "VmSize:\t{} kB", vm_size / 1024).unwrap();
+    for thread in threads {
+        let inner = thread.inner();
+        // 正在执行的线程没有保存的 Context
+        let state = if inner.dead {
+            "dead"
+        } else if inner.sleeping {
+            "sleeping"
+        } else if inner.context.is_none() {
+            "running"
+        } else {
+            "ready"
+        };
+        writeln!(
+            content,
+            "Tid {}:\t{}\tpriority {}",
+            thread.id, state, inner.priority
+        )
+        .unwrap();
+    }
+    content
+}
+
+/// 生成 `/proc/<pid>/maps`
+fn maps(memory_set: &MemorySet) -> String {
+    let mut content = String::new();
+    for segment in memory_set.segments.values() {
+        let flag = |flag: Flags, c: char| if segment.flags.contains(flag) { c } else { '-' };
+        writeln!(
+            content,
+            "{:016x}-{:016x} {}{}{}{} {}",
+            usize::from(segment.range.start),
+            usize::from(segment.range.end),
+            flag(Flags::READABLE, 'r'),
+            flag(Flags::WRITABLE, 'w'),
+            flag(Flags::EXECUTABLE, 'x'),
+            flag(Flags::USER, 'u'),
+            match segment.map_type {
+                MapType::Linear => "linear",
+                MapType::Framed => "framed",
+                MapType::Shared => "shared",
+                MapType::File => "file",
+            }
+        )
+        .unwrap();
+    }
+    content
+}
+
+/// 生成 `/proc/meminfo`
+fn meminfo() -> String {
+    let (total_frames, free_frames) = {
+        let allocator = FRAME_ALLOCATOR.lock();
+        (allocator.total_frames(), allocator.free_frames())
+    };
+    let (heap_total, heap_used) = heap::stats();
+    let cached_pages = PAGE_CACHE.cached_pages();
+    let page_table_frames = PAGE_TABLE_FRAMES.load(Ordering::Relaxed);
+
+    let mut content = String::new();
+    writeln!(content, "MemTotal:\t{} kB", total_frames * PAGE_SIZE / 1024).unwrap();
+    writeln!(content, "MemFree:\t{} kB", free_frames * PAGE_SIZE / 1024).unwrap();
+    writeln!(content, "Cached:\t{} kB", cached_pages * PAGE_SIZE / 1024).unwrap();
+    writeln!(content, "PageTables:\t{} kB", page_table_frames * PAGE_SIZE / 1024).unwrap();
+    writeln!(content, "HeapTotal:\t{} kB", heap_total / 1024).unwrap();
+    writeln!(content, "HeapUsed:\t{} kB", heap_used / 1024).unwrap();
+    content
+}
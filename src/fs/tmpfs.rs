@@ -0,0 +1,561 @@
+//! 内存文件系统 [`TmpFs`]
+//!
+//! 所有数据都存放在内核分配的物理页（[`FrameTracker`]）中，不会写入磁盘。
+//! 支持普通文件、目录和符号链接，挂载在 `/tmp`。
+//!
+//! 节点持有文件系统的强引用，卸载之后仍然打开的文件可以继续使用。
+//! 挂载期间文件系统持有根目录；卸载时由 [`detach`] 释放根目录并清空其中的目录项，
+//! 避免循环引用，此后没有打开的文件随之释放。
+
+use super::*;
+use crate::memory::{frame::FrameTracker, FRAME_ALLOCATOR, PAGE_SIZE};
+use alloc::{
+    collections::BTreeMap,
+    string::{String, ToString},
+    sync::Weak,
+};
+use core::cmp::min;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use spin::RwLock;
+
+/// 内存文件系统
+pub struct TmpFs {
+    /// 自身的引用，用于创建节点，在 [`TmpFs::new`] 中设置
+    this: RwLock<Weak<TmpFs>>,
+    /// 根目录
+    root: RwLock<Weak<TmpINode>>,
+    /// 挂载期间持有根目录，使没有打开的文件也不会被释放
+    mounted: Mutex<Option<Arc<TmpINode>>>,
+    /// 下一个分配的 inode 编号
+    next_id: AtomicUsize,
+    /// 移动目录项时持有，此时其他目录的父目录不会改变
+    rename_lock: Mutex<()>,
+    /// 文件内容已经占用的物理页数
+    used_pages: Mutex<usize>,
+    /// 文件内容最多占用的物理页数，为全部物理页的一半
+    max_pages: usize,
+}
+
+/// 内存文件系统中的一个节点
+pub struct TmpINode {
+    /// inode 编号
+    id: usize,
+    /// 用 `RwLock` 包装可变的部分
+    inner: RwLock<TmpINodeInner>,
+}
+
+/// [`TmpINode`] 中可变的部分
+struct TmpINodeInner {
+    /// 文件类型
+    type_: FileType,
+    /// 权限
+    mode: u16,
+    /// 硬链接数
+    nlinks: usize,
+    /// 文件大小（字节）
+    size: usize,
+    /// 存放文件（或符号链接目标）内容的物理页
+    pages: Vec<FrameTracker>,
+    /// 目录项
+    children: BTreeMap<String, Arc<TmpINode>>,
+    /// 父目录，根目录的父目录是自己
+    parent: Weak<TmpINode>,
+    /// 自身的引用，用于返回 `Arc`
+    this: Weak<TmpINode>,
+    /// 所属的文件系统
+    fs: Arc<TmpFs>,
+}
+
+impl TmpFs {
+    /// 创建一个空的内存文件系统
+    pub fn new() -> Arc<Self> {
+        let fs = Arc::new(Self {
+            this: RwLock::new(Weak::new()),
+            root: RwLock::new(Weak::new()),
+            mounted: Mutex::new(None),
+            next_id: AtomicUsize::new(1),
+            rename_lock: Mutex::new(()),
+            used_pages: Mutex::new(0),
+            max_pages: FRAME_ALLOCATOR.lock().total_frames() / 2,
+        });
+        *fs.this.write() = Arc::downgrade(&fs);
+        let root = fs.new_root();
+        *fs.mounted.lock() = Some(root);
+        fs
+    }
+
+    /// 创建一个节点
+    fn new_inode(&self, type_: FileType, mode: u16) -> Arc<TmpINode> {
+        // 能够调用这个方法时文件系统一定还没有释放
+        let fs = self.this.read().upgrade().unwrap();
+        TmpINode::new(fs.next_id.fetch_add(1, Ordering::Relaxed), fs, type_, mode)
+    }
+
+    /// 创建空的根目录并记录在 `root` 中，根目录的父目录是自己
+    fn new_root(&self) -> Arc<TmpINode> {
+        let root = self.new_inode(FileType::Dir, 0o777);
+        root.inner.write().parent = Arc::downgrade(&root);
+        *self.root.write() = Arc::downgrade(&root);
+        root
+    }
+
+    /// 为文件内容预留 `count` 个物理页，超过容量时返回 [`FsError::NoDeviceSpace`]
+    fn reserve_pages(&self, count: usize) -> Result<()> {
+        let mut used = self.used_pages.lock();
+        if count > self.max_pages - *used {
+            return Err(FsError::NoDeviceSpace);
+        }
+        *used += count;
+        Ok(())
+    }
+
+    /// 归还预留的物理页
+    fn release_pages(&self, count: usize) {
+        *self.used_pages.lock() -= count;
+    }
+}
+
+/// 卸载内存文件系统时调用，释放挂载期间持有的根目录，并像删除一样清空其中的目录项
+///
+/// 此后只有仍然打开的文件及其中的内容会保留下来。`fs` 不是内存文件系统时什么也不做
+pub fn detach(fs: &Arc<dyn FileSystem>) {
+    let root = fs.root_inode();
+    let root = match root.downcast_ref::<TmpINode>() {
+        Some(root) => root,
+        None => return,
+    };
+    let children = {
+        let mut inner = root.inner.write();
+        inner.nlinks = 0;
+        core::mem::take(&mut inner.children)
+    };
+    for child in children.values() {
+        let mut child_inner = child.inner.write();
+        if child_inner.type_ == FileType::Dir {
+            child_inner.nlinks = 0;
+        } else {
+            child_inner.nlinks -= 1;
+        }
+    }
+    let fs = root.inner.read().fs.clone();
+    fs.mounted.lock().take();
+}
+
+impl FileSystem for TmpFs {
+    fn sync(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn root_inode(&self) -> Arc<dyn INode> {
+        let root = self.root.read().upgrade();
+        match root {
+            Some(root) => root,
+            // 卸载之后根目录可能已经释放，此时文件系统中只剩下仍然打开的文件，没有可以访问的目录
+            None => {
+                let root = self.new_root();
+                root.inner.write().nlinks = 0;
+                root
+            }
+        }
+    }
+
+    fn info(&self) -> FsInfo {
+        // 数据存放在物理页中，剩余空间还受到空闲物理页数的限制
+        let free_frames = FRAME_ALLOCATOR.lock().free_frames();
+        let free_pages = min(self.max_pages - *self.used_pages.lock(), free_frames);
+        FsInfo {
+            bsize: PAGE_SIZE,
+            frsize: PAGE_SIZE,
+            blocks: self.max_pages,
+            bfree: free_pages,
+            bavail: free_pages,
+            files: self.next_id.load(Ordering::Relaxed),
+            ffree: usize::max_value(),
+            namemax: 255,
+        }
+    }
+}
+
+impl TmpINode {
+    /// 创建一个节点
+    fn new(id: usize, fs: Arc<TmpFs>, type_: FileType, mode: u16) -> Arc<Self> {
+        let inode = Arc::new(Self {
+            id,
+            inner: RwLock::new(TmpINodeInner {
+                type_,
+                mode,
+                nlinks: if type_ == FileType::Dir { 2 } else { 1 },
+                size: 0,
+                pages: Vec::new(),
+                children: BTreeMap::new(),
+                parent: Weak::new(),
+                this: Weak::new(),
+                fs,
+            }),
+        });
+        inode.inner.write().this = Arc::downgrade(&inode);
+        inode
+    }
+
+    /// 从自身向上直到根目录的路径上是否有 `ancestor`，包括自身
+    ///
+    /// 需要持有 [`TmpFs::rename_lock`]，这样途经的目录不会被移动
+    fn is_within(&self, ancestor: &TmpINode) -> bool {
+        let mut current = self.inner.read().this.upgrade().unwrap();
+        loop {
+            if core::ptr::eq(&*current, ancestor) {
+                return true;
+            }
+            let parent = current.inner.read().parent.upgrade();
+            match parent {
+                // 根目录的父目录是自己
+                Some(parent) if !Arc::ptr_eq(&parent, &current) => current = parent,
+                _ => return false,
+            }
+        }
+    }
+}
+
+/// 节点释放时归还文件内容占用的物理页
+impl Drop for TmpINode {
+    fn drop(&mut self) {
+        let inner = self.inner.get_mut();
+        inner.fs.release_pages(inner.pages.len());
+    }
+}
+
+impl TmpINodeInner {
+    /// 调整文件大小，新增部分填零
+    ///
+    /// 空间不足时返回 [`FsError::NoDeviceSpace`]，文件保持不变
+    fn resize(&mut self, len: usize) -> Result<()> {
+        let page_count = len
+            .checked_add(PAGE_SIZE - 1)
+            .ok_or(FsError::NoDeviceSpace)?
+            / PAGE_SIZE;
+        if page_count > self.pages.len() {
+            let count = page_count - self.pages.len();
+            self.fs.reserve_pages(count)?;
+            // 先分配所有新增的页，中途失败时全部归还
+            let mut pages = Vec::with_capacity(count);
+            for _ in 0..count {
+                let frame = FRAME_ALLOCATOR.lock().alloc();
+                match frame {
+                    Ok(mut frame) => {
+                        frame.copy_from_slice(&[0u8; PAGE_SIZE]);
+                        pages.push(frame);
+                    }
+                    Err(_) => {
+                        drop(pages);
+                        self.fs.release_pages(count);
+                        return Err(FsError::NoDeviceSpace);
+                    }
+                }
+            }
+            self.pages.append(&mut pages);
+        } else {
+            self.fs.release_pages(self.pages.len() - page_count);
+            self.pages.truncate(page_count);
+        }
+        // 缩小时将最后一页中超出的部分清零，之后再扩大时读到的才是零
+        if len < self.size && len % PAGE_SIZE != 0 {
+            let last = self.pages.last_mut().unwrap();
+            for byte in last[len % PAGE_SIZE..].iter_mut() {
+                *byte = 0;
+            }
+        }
+        self.size = len;
+        Ok(())
+    }
+
+    /// 是否为可以读写内容的节点
+    fn check_readable(&self) -> Result<()> {
+        match self.type_ {
+            FileType::File | FileType::SymLink => Ok(()),
+            FileType::Dir => Err(FsError::IsDir),
+            _ => Err(FsError::NotFile),
+        }
+    }
+
+    /// 是否为目录
+    fn check_dir(&self) -> Result<()> {
+        match self.type_ {
+            FileType::Dir => Ok(()),
+            _ => Err(FsError::NotDir),
+        }
+    }
+}
+
+impl INode for TmpINode {
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
+        let inner = self.inner.read();
+        inner.check_readable()?;
+        if offset >= inner.size {
+            return Ok(0);
+        }
+        let end = min(inner.size, offset.saturating_add(buf.len()));
+        let mut position = offset;
+        while position < end {
+            let page = &inner.pages[position / PAGE_SIZE];
+            let page_offset = position % PAGE_SIZE;
+            let length = min(PAGE_SIZE - page_offset, end - position);
+            buf[position - offset..position - offset + length]
+                .copy_from_slice(&page[page_offset..page_offset + length]);
+            position += length;
+        }
+        Ok(end - offset)
+    }
+
+    fn write_at(&self, offset: usize, buf: &[u8]) -> Result<usize> {
+        let mut inner = self.inner.write();
+        inner.check_readable()?;
+        let end = offset.checked_add(buf.len()).ok_or(FsError::InvalidParam)?;
+        if end > inner.size {
+            inner.resize(end)?;
+        }
+        let mut position = offset;
+        while position < end {
+            let page = &mut inner.pages[position / PAGE_SIZE];
+            let page_offset = position % PAGE_SIZE;
+            let length = min(PAGE_SIZE - page_offset, end - position);
+            page[page_offset..page_offset + length]
+                .copy_from_slice(&buf[position - offset..position - offset + length]);
+            position += length;
+        }
+        Ok(buf.len())
+    }
+
+    fn poll(&self) -> Result<PollStatus> {
+        Ok(PollStatus {
+            read: true,
+            write: true,
+            error: false,
+        })
+    }
+
+    fn metadata(&self) -> Result<Metadata> {
+        let inner = self.inner.read();
+        Ok(Metadata {
+            dev: 0,
+            inode: self.id,
+            size: inner.size,
+            blk_size: PAGE_SIZE,
+            blocks: inner.pages.len(),
+            atime: Timespec { sec: 0, nsec: 0 },
+            mtime: Timespec { sec: 0, nsec: 0 },
+            ctime: Timespec { sec: 0, nsec: 0 },
+            type_: inner.type_,
+            mode: inner.mode,
+            nlinks: inner.nlinks,
+            uid: 0,
+            gid: 0,
+            rdev: 0,
+        })
+    }
+
+    fn set_metadata(&self, metadata: &Metadata) -> Result<()> {
+        self.inner.write().mode = metadata.mode;
+        Ok(())
+    }
+
+    fn sync_all(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn sync_data(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn resize(&self, len: usize) -> Result<()> {
+        let mut inner = self.inner.write();
+        match inner.type_ {
+            FileType::File => inner.resize(len),
+            FileType::Dir => Err(FsError::IsDir),
+            _ => Err(FsError::NotFile),
+        }
+    }
+
+    fn create(&self, name: &str, type_: FileType, mode: u32) -> Result<Arc<dyn INode>> {
+        let mut inner = self.inner.write();
+        inner.check_dir()?;
+        if inner.nlinks == 0 {
+            return Err(FsError::DirRemoved);
+        }
+        match type_ {
+            FileType::File | FileType::Dir | FileType::SymLink => {}
+            _ => return Err(FsError::NotSupported),
+        }
+        if name == "." || name == ".." || inner.children.contains_key(name) {
+            return Err(FsError::EntryExist);
+        }
+        let inode = inner.fs.new_inode(type_, mode as u16);
+        inode.inner.write().parent = inner.this.clone();
+        if type_ == FileType::Dir {
+            inner.nlinks += 1;
+        }
+        inner.children.insert(name.to_string(), inode.clone());
+        Ok(inode)
+    }
+
+    fn link(&self, name: &str, other: &Arc<dyn INode>) -> Result<()> {
+        let other = other
+            .as_any_ref()
+            .downcast_ref::<TmpINode>()
+            .ok_or(FsError::NotSameFs)?;
+        let mut inner = self.inner.write();
+        inner.check_dir()?;
+        if name == "." || name == ".." || inner.children.contains_key(name) {
+            return Err(FsError::EntryExist);
+        }
+        let mut other_inner = other.inner.write();
+        if !Arc::ptr_eq(&other_inner.fs, &inner.fs) {
+            return Err(FsError::NotSameFs);
+        }
+        if other_inner.type_ == FileType::Dir {
+            return Err(FsError::IsDir);
+        }
+        other_inner.nlinks += 1;
+        let other = other_inner.this.upgrade().unwrap();
+        drop(other_inner);
+        inner.children.insert(name.to_string(), other);
+        Ok(())
+    }
+
+    fn unlink(&self, name: &str) -> Result<()> {
+        if name == "." || name == ".." {
+            return Err(FsError::IsDir);
+        }
+        let mut inner = self.inner.write();
+        inner.check_dir()?;
+        let child = inner
+            .children
+            .get(name)
+            .ok_or(FsError::EntryNotFound)?
+            .clone();
+        let mut child_inner = child.inner.write();
+        if child_inner.type_ == FileType::Dir {
+            if !child_inner.children.is_empty() {
+                return Err(FsError::DirNotEmpty);
+            }
+            // 目录本身的 "." 和父目录中的 ".."
+            child_inner.nlinks = 0;
+            inner.nlinks -= 1;
+        } else {
+            child_inner.nlinks -= 1;
+        }
+        drop(child_inner);
+        inner.children.remove(name);
+        Ok(())
+    }
+
+    fn move_(&self, old_name: &str, target: &Arc<dyn INode>, new_name: &str) -> Result<()> {
+        if old_name == "." || old_name == ".." || new_name == "." || new_name == ".." {
+            return Err(FsError::IsDir);
+        }
+        let target = target
+            .as_any_ref()
+            .downcast_ref::<TmpINode>()
+            .ok_or(FsError::NotSameFs)?;
+        let fs = self.inner.read().fs.clone();
+        if !Arc::ptr_eq(&fs, &target.inner.read().fs) {
+            return Err(FsError::NotSameFs);
+        }
+        let _rename = fs.rename_lock.lock();
+        if core::ptr::eq(self, target) {
+            let mut inner = self.inner.write();
+            inner.check_dir()?;
+            if inner.children.contains_key(new_name) {
+                return Err(FsError::EntryExist);
+            }
+            let child = inner.children.remove(old_name).ok_or(FsError::EntryNotFound)?;
+            inner.children.insert(new_name.to_string(), child);
+            return Ok(());
+        }
+        let child = {
+            let inner = self.inner.read();
+            inner.check_dir()?;
+            inner.children.get(old_name).cloned().ok_or(FsError::EntryNotFound)?
+        };
+        // 目录不能移动到自身或者自身的子目录中，否则会形成无法访问的环
+        if target.is_within(&child) {
+            return Err(FsError::InvalidParam);
+        }
+        // 与其他操作先锁父目录再锁子节点的顺序一致，祖先目录先上锁；没有祖先关系时按 inode 编号上锁
+        let self_first = if target.is_within(self) {
+            true
+        } else if self.is_within(target) {
+            false
+        } else {
+            self.id < target.id
+        };
+        let (mut inner, mut target_inner) = if self_first {
+            let inner = self.inner.write();
+            (inner, target.inner.write())
+        } else {
+            let target_inner = target.inner.write();
+            (self.inner.write(), target_inner)
+        };
+        target_inner.check_dir()?;
+        if target_inner.nlinks == 0 {
+            return Err(FsError::DirRemoved);
+        }
+        if target_inner.children.contains_key(new_name) {
+            return Err(FsError::EntryExist);
+        }
+        // 上锁之前目录项可能已经被删除或替换
+        match inner.children.get(old_name) {
+            Some(entry) if Arc::ptr_eq(entry, &child) => {}
+            _ => return Err(FsError::EntryNotFound),
+        }
+        inner.children.remove(old_name);
+        {
+            let mut child_inner = child.inner.write();
+            if child_inner.type_ == FileType::Dir {
+                child_inner.parent = target_inner.this.clone();
+                inner.nlinks -= 1;
+                target_inner.nlinks += 1;
+            }
+        }
+        target_inner.children.insert(new_name.to_string(), child);
+        Ok(())
+    }
+
+    fn find(&self, name: &str) -> Result<Arc<dyn INode>> {
+        let inner = self.inner.read();
+        inner.check_dir()?;
+        match name {
+            "." => Ok(inner.this.upgrade().unwrap()),
+            ".." => Ok(inner.parent.upgrade().ok_or(FsError::DirRemoved)?),
+            name => inner
+                .children
+                .get(name)
+                .map(|child| child.clone() as Arc<dyn INode>)
+                .ok_or(FsError::EntryNotFound),
+        }
+    }
+
+    fn get_entry(&self, id: usize) -> Result<String> {
+        let inner = self.inner.read();
+        inner.check_dir()?;
+        match id {
+            0 => Ok(".".to_string()),
+            1 => Ok("..".to_string()),
+            id => inner
+                .children
+                .keys()
+                .nth(id - 2)
+                .cloned()
+                .ok_or(FsError::EntryNotFound),
+        }
+    }
+
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        self.inner.read().fs.clone()
+    }
+
+    /// This is used to implement dynamics cast.
+    /// Simply return self in the implement of the function.
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+}
@@ -67,6 +67,13 @@ impl Context {
         self
     }
 
+    /// 是否是在中断处理流程中发生的异常
+    ///
+    /// `interrupt.asm` 会在这种情况下将不使用的 0 号寄存器位置标记为 1
+    pub fn is_nested(&self) -> bool {
+        self.x[0] != 0
+    }
+
     /// 为线程构建初始 `Context`
     pub fn new(
         stack_top: usize,
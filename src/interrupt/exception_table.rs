@@ -0,0 +1,38 @@
+//! 异常修复表
+//!
+//! 内核中会访问用户内存的汇编代码（例如 `memory/user.asm` 中的 `__copy_user`）
+//! 将可能出错的指令区间和对应的修复代码登记在 `__ex_table` 段中，
+//! 链接脚本将它们收集在 `__ex_table_start` 和 `__ex_table_end` 之间。
+//!
+//! 内核态发生访存异常时，如果出错的指令位于某个区间内，
+//! 就将 `sepc` 改为修复代码的地址，由修复代码向调用者返回错误。
+
+use core::mem::size_of;
+
+/// 一条修复记录，与汇编中的 `.dword start, end, fixup` 对应
+#[repr(C)]
+struct ExceptionTableEntry {
+    /// 可能出错的指令区间的起始地址
+    start: usize,
+    /// 指令区间的结束地址（不含）
+    end: usize,
+    /// 修复代码的地址
+    fixup: usize,
+}
+
+/// 查找出错指令对应的修复代码地址
+pub fn search(address: usize) -> Option<usize> {
+    extern "C" {
+        /// 链接脚本中修复表的起始位置
+        fn __ex_table_start();
+        /// 链接脚本中修复表的结束位置
+        fn __ex_table_end();
+    }
+    let start = __ex_table_start as usize;
+    let count = (__ex_table_end as usize - start) / size_of::<ExceptionTableEntry>();
+    let table = unsafe { core::slice::from_raw_parts(start as *const ExceptionTableEntry, count) };
+    table
+        .iter()
+        .find(|entry| (entry.start..entry.end).contains(&address))
+        .map(|entry| entry.fixup)
+}
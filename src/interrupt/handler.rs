@@ -1,18 +1,41 @@
 // 为了让硬件能够找到我们编写的 __interrupt 入口，在操作系统初始化时，需要将其写入 stvec 寄存器中
 use super::context::Context;
+use super::exception_table;
 use super::timer;
 use crate::process::PROCESSOR;
 use riscv::register::{
     stvec, sie,
-    scause::{Exception, Interrupt, Scause, Trap}
+    scause::{Exception, Interrupt, Scause, Trap},
+    sstatus::SPP,
 };
 use crate::sbi::console_getchar;
 use crate::memory::*;
 use crate::fs::STDIN;
 use crate::kernel::syscall_handler;
+use core::sync::atomic::{AtomicUsize, Ordering};
 
 global_asm!(include_str!("./interrupt.asm"));
 
+/// 各类中断的名字，与 [`INTERRUPT_COUNTS`] 一一对应
+pub const INTERRUPT_NAMES: [&str; 6] = [
+    "timer",
+    "external",
+    "syscall",
+    "breakpoint",
+    "loadfault",
+    "other",
+];
+
+/// 各类中断发生的次数
+pub static INTERRUPT_COUNTS: [AtomicUsize; 6] = [
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+];
+
 /// 初始化中断处理
 ///
 /// 把中断入口 `__interrupt` 写入 `stvec` 中，并且开启中断使能
@@ -47,6 +70,11 @@ pub fn init() {
 /// 如果不需要切换，那么直接返回原本的 Context 即可。
 #[no_mangle]
 pub fn handle_interrupt(context: &mut Context, scause: Scause, stval: usize) -> *mut Context {
+    count_interrupt(&scause);
+    // 内核态的异常可能发生在持有 PROCESSOR 等锁的时候，需要最先处理
+    if let Some(context) = kernel_exception(context, &scause, stval) {
+        return context;
+    }
     // 首先检查线程是否已经结束（内核线程会自己设置标记来结束自己）
     {
         let mut processor = PROCESSOR.lock();
@@ -65,6 +93,10 @@ pub fn handle_interrupt(context: &mut Context, scause: Scause, stval: usize) ->
         Trap::Exception(Exception::Breakpoint) => breakpoint(context),
         // Load Fault, 访问不存在地址
         Trap::Exception(Exception::LoadFault) => loadfault(context, stval),
+        // 缺页，可能是按需加载的文件页面
+        Trap::Exception(Exception::LoadPageFault)
+        | Trap::Exception(Exception::StorePageFault)
+        | Trap::Exception(Exception::InstructionPageFault) => page_fault(context, scause, stval),
         // 系统调用
         Trap::Exception(Exception::UserEnvCall) => syscall_handler(context),
         // 时钟中断
@@ -77,6 +109,50 @@ pub fn handle_interrupt(context: &mut Context, scause: Scause, stval: usize) ->
     // panic!("Interrupted: {:?}", scause.cause()); // panic之后就退出了，没有返回
 }
 
+/// 处理内核态出现的异常
+///
+/// 访存出错的指令登记在 [`exception_table`] 中时，跳转到修复代码，由其向调用者返回错误。
+/// 中断处理流程中出现而又无法修复的异常说明内核出错，直接 panic。
+/// 其他情况返回 `None`，按一般的中断处理
+fn kernel_exception(context: &mut Context, scause: &Scause, stval: usize) -> Option<*mut Context> {
+    let is_memory_fault = matches!(
+        scause.cause(),
+        Trap::Exception(Exception::LoadFault)
+            | Trap::Exception(Exception::StoreFault)
+            | Trap::Exception(Exception::LoadPageFault)
+            | Trap::Exception(Exception::StorePageFault)
+            | Trap::Exception(Exception::InstructionPageFault)
+    );
+    if is_memory_fault && context.sstatus.spp() == SPP::Supervisor {
+        if let Some(fixup) = exception_table::search(context.sepc) {
+            context.sepc = fixup;
+            return Some(context);
+        }
+    }
+    if context.is_nested() {
+        panic!(
+            "Unresolved exception in interrupt handler: {:?}\n{:x?}\n  stval = 0x{:016x}",
+            scause.cause(),
+            context,
+            stval
+        );
+    }
+    None
+}
+
+/// 按 [`INTERRUPT_NAMES`] 的分类记录一次中断
+fn count_interrupt(scause: &Scause) {
+    let index = match scause.cause() {
+        Trap::Interrupt(Interrupt::SupervisorTimer) => 0,
+        Trap::Interrupt(Interrupt::SupervisorExternal) => 1,
+        Trap::Exception(Exception::UserEnvCall) => 2,
+        Trap::Exception(Exception::Breakpoint) => 3,
+        Trap::Exception(Exception::LoadFault) => 4,
+        _ => 5,
+    };
+    INTERRUPT_COUNTS[index].fetch_add(1, Ordering::Relaxed);
+}
+
 /// 处理 ebreak 断点
 /// 
 /// 继续执行，其中 `sepc` 增加 2 字节，以跳过当前这条 `ebreak` 指令
@@ -103,9 +179,23 @@ fn loadfault(context: &Context, stval: usize) -> *mut Context {
         println!("SUCCESS!");
     }
     println!("LoadFault: \n{:?}\n  stval = 0x{:016x}", context, stval);
-    PROCESSOR.lock().kill_current_thread(); // 无法处理，杀死当前线程
-    // 跳转到 PROCESSOR 调度的下一个线程
-    PROCESSOR.lock().prepare_next_thread()
+    kill_current_thread() // 无法处理，杀死当前线程
+}
+
+/// 处理缺页异常
+///
+/// 访问的是按需加载的文件页面时，建立映射后重新执行出错的指令，否则按未能解决的异常处理
+fn page_fault(context: &mut Context, scause: Scause, stval: usize) -> *mut Context {
+    let process = PROCESSOR.lock().current_thread().process.clone();
+    let resolved = process
+        .inner()
+        .memory_set
+        .handle_page_fault(VirtualAddress(stval));
+    if resolved {
+        context
+    } else {
+        fault(context, scause, stval)
+    }
 }
 
 /// 处理外部中断，只实现了键盘输入
@@ -114,12 +204,13 @@ fn supervisor_external(context: &mut Context) -> *mut Context {
     if c <= 255 {
         if c == 3 { // 当键盘按下 Ctrl + C 时，操作系统应该能够捕捉到中断。OS捕获该信号并结束当前运行的线程
             println!("^C: Thread {} killed.", PROCESSOR.lock().current_thread().id);
-            PROCESSOR.lock().kill_current_thread();
-            return PROCESSOR.lock().prepare_next_thread();
+            return kill_current_thread();
         } else if c == 'f' as usize { // 按 F 进入 fork. 
             // fork 后应当为目前的线程复制一份几乎一样的拷贝，新线程与旧线程同属一个进程，公用页表和大部分内存空间，而新线程的栈是一份拷贝。
             print!("F: ");
-            PROCESSOR.lock().fork_current_thread(context);
+            if let Err(message) = PROCESSOR.lock().fork_current_thread(context) {
+                println!("fork failed: {}", message);
+            }
         } else {
             if c == '\r' as usize {
                 c = '\n' as usize;
@@ -139,7 +230,17 @@ fn fault(context: &mut Context, scause: Scause, stval: usize) -> *mut Context {
         context,
         stval
     );
-    PROCESSOR.lock().kill_current_thread(); // 无法处理，杀死当前线程
-    // 跳转到 PROCESSOR 调度的下一个线程
+    kill_current_thread() // 无法处理，杀死当前线程
+}
+
+/// 杀死当前线程，跳转到 PROCESSOR 调度的下一个线程
+///
+/// 用户进程的最后一个线程被杀死时进程以 -1 结束，由父进程回收
+fn kill_current_thread() -> *mut Context {
+    let thread = PROCESSOR.lock().current_thread();
+    if thread.process.is_user {
+        thread.process.exit_thread(&thread, -1);
+    }
+    PROCESSOR.lock().kill_current_thread();
     PROCESSOR.lock().prepare_next_thread()
 }
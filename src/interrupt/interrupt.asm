@@ -44,12 +44,28 @@ __interrupt:
     
     # 交换 sp 和 sscratch（sp切换到内核栈, sscratch指向旧线程的栈顶）
     csrrw   sp, sscratch, sp
+    # 处理中断期间 sscratch 为 0。如果换出的 sp 为 0，说明异常发生在中断处理流程中
+    # （例如系统调用访问用户内存时缺页），此时已经在内核栈上，继续使用原来的 sp
+    bnez    sp, 1f
+    csrr    sp, sscratch
 
+    # 在栈上开辟 Context 所需的空间，存储 Context
+    addi    sp, sp, -CONTEXT_SIZE*REG_SIZE
+    SAVE    x1, 1
+    # 在 x0 的位置上标记这是嵌套的异常
+    li      x1, 1
+    SAVE    x1, 0
+    j       2f
+
+1:
     # 在栈上开辟 Context 所需的空间，存储旧线程的上下文
     addi    sp, sp, -CONTEXT_SIZE*REG_SIZE
 
-    # 保存通用寄存器，除了 x0（固定为 0）
+    # 保存通用寄存器，x0 固定为 0，其位置用于标记嵌套的异常
     SAVE    x1, 1
+    SAVE    x0, 0
+
+2:
     # 将本来的栈地址 sp（即 x2）保存
     csrr    x1, sscratch
     SAVE    x1, 2
@@ -66,6 +82,9 @@ __interrupt:
     SAVE    t0, 32
     SAVE    t1, 33
 
+    # 处理中断期间将 sscratch 置为 0，用于识别嵌套的异常
+    csrw    sscratch, zero
+
     # 调用 handle_interrupt, 传入参数, 通过汇编实现
     # context: &mut Context
     mv      a0, sp
@@ -97,8 +116,12 @@ __restore:
     csrw    sepc, t1
 
     # 将内核栈地址写入 sscratch
+    # 嵌套的异常返回后仍在中断处理流程中，sscratch 保持为 0
+    LOAD    t0, 0
+    bnez    t0, 1f
     addi    t0, sp, CONTEXT_SIZE * REG_SIZE
     csrw    sscratch, t0
+1:
 
     # 恢复通用寄存器
     LOAD    x1, 1
@@ -2,10 +2,13 @@
 //! 
 //! 
 mod handler;
+mod exception_table;
 mod context;
 mod timer;
 
 pub use context::Context;
+pub use handler::{INTERRUPT_COUNTS, INTERRUPT_NAMES};
+pub use timer::{ticks, uptime_centisecs};
 
 /// 初始化中断相关的子模块, 简单封装 一些 init
 /// 
@@ -34,9 +34,22 @@ fn set_next_timeout() {
     set_timer(time::read() + INTERVAL);
 }
 
+/// 时钟频率，QEMU virt 平台为 10 MHz
+const CLOCK_FREQ: usize = 10_000_000;
+
 /// 触发时钟中断计数
 pub static mut TICKS: usize = 0;
 
+/// 已经触发的时钟中断次数
+pub fn ticks() -> usize {
+    unsafe { TICKS }
+}
+
+/// 根据时钟中断次数估算的运行时间，单位为 10 毫秒
+pub fn uptime_centisecs() -> usize {
+    ticks() * INTERVAL / (CLOCK_FREQ / 100)
+}
+
 /// 每一次时钟中断时调用
 /// 由于没有一个接口来设置固定重复的时间中断间隔，因此我们需要在每一次时钟中断时，设置再下一次的时钟中断。
 /// 设置下一次时钟中断，同时计数 +1
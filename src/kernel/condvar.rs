@@ -25,4 +25,12 @@ impl Condvar {
             PROCESSOR.lock().wake_thread(thread);
         }
     }
+
+    /// 唤起所有等待此条件变量的线程
+    pub fn notify_all(&self) {
+        let watchers: VecDeque<_> = self.watchers.lock().drain(..).collect();
+        for thread in watchers {
+            PROCESSOR.lock().wake_thread(thread);
+        }
+    }
 }
@@ -1,10 +1,14 @@
 //! 文件相关的内核功能
 
 use super::*;
-use core::slice::from_raw_parts_mut;
-use core::str;
-use core::slice;
-use crate::fs::ROOT_INODE;
+use crate::fs::{self, PATH_MAX};
+use crate::memory::{user::read_c_str, MemorySet, UserSlice, PAGE_SIZE};
+use alloc::{string::String, vec};
+
+/// 读写文件时一次拷贝到内核缓冲区的最大字节数
+///
+/// 内核堆只有几 MiB，不能按照用户给出的长度分配缓冲区
+pub(super) const IO_BUFFER_SIZE: usize = 16 * PAGE_SIZE;
 
 // 使用条件变量之后，
 // 对于线程而言, 读取字符的系统调用是阻塞的, 因为在等待有效输入之前线程都会暂停。
@@ -12,66 +16,127 @@ use crate::fs::ROOT_INODE;
 
 /// 从指定的文件中读取字符
 ///
-/// 如果缓冲区暂无数据，返回 0；出现错误返回 -1
-pub(super) fn sys_read(fd: usize, buffer: *mut u8, size: usize) -> SyscallResult {
+/// 如果缓冲区暂无数据，返回 0；一次最多读取 [`IO_BUFFER_SIZE`] 个字节
+pub(super) fn sys_read(fd: usize, buffer: usize, size: usize) -> KernelResult<SyscallResult> {
     // 从进程中获取 inode
     let process = PROCESSOR.lock().current_thread().process.clone();
-    if let Some(inode) = process.inner().descriptors.get(fd) {
-        // 从系统调用传入的参数生成缓冲区
-        let buffer = unsafe { from_raw_parts_mut(buffer, size) };
-        // 尝试读取
-        if let Ok(ret) = inode.read_at(0, buffer) {
-            let ret = ret as isize;
-            if ret > 0 {
-                return SyscallResult::Proceed(ret);
-            }
-            if ret == 0 {
-                return SyscallResult::Park(ret);
-            }
-        }
+    let size = size.min(IO_BUFFER_SIZE);
+    let buffer = UserSlice::new(buffer, size);
+    // 先检查缓冲区，避免读出数据之后才发现无法写入
+    buffer
+        .check_writable(&mut process.inner().memory_set)
+        .map_err(|_| KernelError::Fault)?;
+    // 先取出文件再释放进程的锁，读取 procfs 时可能需要再次访问进程
+    let file = process.inner().file(fd).ok_or(KernelError::BadDescriptor)?;
+    // 读入内核的缓冲区，再拷贝给用户
+    let mut data = vec![0; size];
+    let ret = file.read(&mut data)?;
+    if ret == 0 {
+        return Ok(SyscallResult::Park(0));
     }
-    SyscallResult::Proceed(-1)
+    buffer
+        .write(&mut process.inner().memory_set, &data[..ret])
+        .map_err(|_| KernelError::Fault)?;
+    Ok(SyscallResult::Proceed(ret as isize))
 }
 
 /// 将字符写入指定的文件
-pub(super) fn sys_write(fd: usize, buffer: *mut u8, size: usize) -> SyscallResult {
+///
+/// 已经写入数据时，后面的部分出错则返回已经写入的字节数
+pub(super) fn sys_write(fd: usize, buffer: usize, size: usize) -> KernelResult<SyscallResult> {
     // 从进程中获取 inode
     let process = PROCESSOR.lock().current_thread().process.clone();
-    if let Some(inode) = process.inner().descriptors.get(fd) {
-        // 从系统调用传入的参数生成缓冲区
-        let buffer = unsafe { from_raw_parts_mut(buffer, size) };
-        // 尝试写入
-        if let Ok(ret) = inode.write_at(0, buffer) {
-            let ret = ret as isize;
-            if ret >= 0 {
-                return SyscallResult::Proceed(ret);
+    let file = process.inner().file(fd).ok_or(KernelError::BadDescriptor)?;
+    // 正在运行的程序直接映射了文件的缓存页，不能修改
+    if fs::PAGE_CACHE.write_denied(&file.inode) {
+        return Err(KernelError::TextBusy);
+    }
+    // 每次将至多 IO_BUFFER_SIZE 个字节拷贝到内核中再写入
+    let mut written = 0;
+    while written < size {
+        let chunk = UserSlice::new(buffer + written, (size - written).min(IO_BUFFER_SIZE));
+        let result = chunk
+            .read(&mut process.inner().memory_set)
+            .map_err(|_| KernelError::Fault)
+            .and_then(|data| file.write(&data).map_err(KernelError::from));
+        match result {
+            Ok(count) => {
+                written += count;
+                // 没有全部写入时不再写后面的部分
+                if count < chunk.len() {
+                    break;
+                }
             }
+            Err(error) if written == 0 => return Err(error),
+            Err(_) => break,
         }
     }
-    SyscallResult::Proceed(-1)
+    Ok(SyscallResult::Proceed(written as isize))
 }
 
 // 将一个文件打包进用户镜像，并让一个用户进程读取它并打印其内容。
 // sys_open: 将文件描述符加入进程的 descriptors 中，然后通过 sys_read 来读取。
-pub(super) fn sys_open(buffer: *mut u8, size: usize) -> SyscallResult {
-    let name = unsafe {
-        let slice = slice::from_raw_parts(buffer, size);
-        str::from_utf8(slice).unwrap()
-    };
-    // 从文件系统中找到程序
-    let file = ROOT_INODE.find(name).unwrap();
+pub(super) fn sys_open(buffer: usize, size: usize) -> KernelResult<SyscallResult> {
     let process = PROCESSOR.lock().current_thread().process.clone();
+    if size > PATH_MAX {
+        return Err(KernelError::InvalidArgument);
+    }
+    let name = UserSlice::new(buffer, size)
+        .read(&mut process.inner().memory_set)
+        .map_err(|_| KernelError::Fault)?;
+    let name = String::from_utf8(name).map_err(|_| KernelError::InvalidArgument)?;
+    // 从文件系统中找到程序
+    let file = fs::lookup(&name)?;
     // 将文件描述符加入进程的 descriptors 中
-    process.inner().descriptors.push(file);
-    SyscallResult::Proceed(
-        (PROCESSOR
-            .lock()
-            .current_thread()
-            .process
-            .clone()
-            .inner()
-            .descriptors
-            .len() - 1
-        ) as isize,
-    )
+    let fd = process.inner().add_file(fs::FileHandle::new(file));
+    Ok(SyscallResult::Proceed(fd as isize))
+}
+
+/// 读取用户传入的路径等以 `\0` 结尾的字符串
+pub(super) fn read_path(memory_set: &mut MemorySet, address: usize) -> KernelResult<String> {
+    read_c_str(memory_set, address, PATH_MAX).map_err(|_| KernelError::Fault)
+}
+
+/// 将 `source` 上类型为 `fstype` 的文件系统挂载到目录 `target`
+///
+/// 三个参数均为以 `\0` 结尾的字符串，成功返回 0
+pub(super) fn sys_mount(source: usize, target: usize, fstype: usize) -> KernelResult<SyscallResult> {
+    let (source, target, fstype) = {
+        let process = PROCESSOR.lock().current_thread().process.clone();
+        let memory_set = &mut process.inner().memory_set;
+        (
+            read_path(memory_set, source)?,
+            read_path(memory_set, target)?,
+            read_path(memory_set, fstype)?,
+        )
+    };
+    fs::mount(&source, &target, &fstype)?;
+    Ok(SyscallResult::Proceed(0))
+}
+
+/// 卸载目录 `target` 上的文件系统
+pub(super) fn sys_umount(target: usize) -> KernelResult<SyscallResult> {
+    let process = PROCESSOR.lock().current_thread().process.clone();
+    let target = read_path(&mut process.inner().memory_set, target)?;
+    fs::umount(&target)?;
+    Ok(SyscallResult::Proceed(0))
+}
+
+/// 将所有文件系统中尚未写回的数据写入设备
+///
+/// 成功返回 0
+pub(super) fn sys_sync() -> KernelResult<SyscallResult> {
+    fs::sync_all()?;
+    Ok(SyscallResult::Proceed(0))
+}
+
+/// 将文件描述符对应的文件写回设备
+///
+/// 先写回文件自身的元数据，再写回所在文件系统的缓存。成功返回 0
+pub(super) fn sys_fsync(fd: usize) -> KernelResult<SyscallResult> {
+    let process = PROCESSOR.lock().current_thread().process.clone();
+    let inode = process.inner().file(fd).ok_or(KernelError::BadDescriptor)?.inode.clone();
+    inode.sync_all()?;
+    inode.fs().sync()?;
+    Ok(SyscallResult::Proceed(0))
 }
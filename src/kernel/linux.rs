@@ -0,0 +1,441 @@
+//! Linux riscv64 系统调用接口
+//!
+//! 编号和参数与 Linux 相同，出错时返回负的错误码，使不经修改的静态链接（例如 musl）程序可以运行。
+//! 进程的 [`SyscallAbi`] 为 `Linux` 时，[`syscall_handler`] 按照这里的表分发系统调用。
+
+use super::*;
+use crate::fs as vfs;
+use crate::memory::{
+    elf::read_headers, user, Flags, MemoryResult, MemorySet, Range, UserPtr, UserSlice,
+    VirtualAddress,
+};
+use alloc::{string::String, vec, vec::Vec};
+use xmas_elf::ElfFile;
+
+const SYS_IOCTL: usize = 29;
+const SYS_UMOUNT2: usize = 39;
+const SYS_MOUNT: usize = 40;
+const SYS_OPENAT: usize = 56;
+const SYS_CLOSE: usize = 57;
+const SYS_READ: usize = 63;
+const SYS_WRITE: usize = 64;
+const SYS_READV: usize = 65;
+const SYS_WRITEV: usize = 66;
+const SYS_SYNC: usize = 81;
+const SYS_FSYNC: usize = 82;
+const SYS_EXIT: usize = 93;
+const SYS_EXIT_GROUP: usize = 94;
+const SYS_SET_TID_ADDRESS: usize = 96;
+const SYS_SCHED_YIELD: usize = 124;
+const SYS_RT_SIGACTION: usize = 134;
+const SYS_RT_SIGPROCMASK: usize = 135;
+const SYS_GETPID: usize = 172;
+const SYS_GETPPID: usize = 173;
+const SYS_GETUID: usize = 174;
+const SYS_GETEUID: usize = 175;
+const SYS_GETGID: usize = 176;
+const SYS_GETEGID: usize = 177;
+const SYS_GETTID: usize = 178;
+const SYS_BRK: usize = 214;
+const SYS_MUNMAP: usize = 215;
+const SYS_CLONE: usize = 220;
+const SYS_EXECVE: usize = 221;
+const SYS_MMAP: usize = 222;
+const SYS_MPROTECT: usize = 226;
+const SYS_WAIT4: usize = 260;
+
+/// `openat` 中表示相对于当前目录
+const AT_FDCWD: isize = -100;
+/// `readv` / `writev` 最多接受的 `iovec` 个数
+const IOV_MAX: usize = 1024;
+/// `execve` 最多接受的参数和环境变量个数
+const ARG_MAX: usize = 4096;
+/// `execve` 的参数和环境变量总共最多占用的字节数，包括结尾的 `\0` 和指向它们的指针
+const ARG_BYTES_MAX: usize = 128 * 1024;
+
+/// 与父进程共享地址空间
+const CLONE_VM: usize = 0x100;
+/// 在同一进程中创建线程
+const CLONE_THREAD: usize = 0x1_0000;
+/// 设置新线程的 `tp`
+const CLONE_SETTLS: usize = 0x8_0000;
+/// 将新线程 ID 写入父进程的 `ptid`
+const CLONE_PARENT_SETTID: usize = 0x10_0000;
+/// 新线程结束时将 `ctid` 清零
+const CLONE_CHILD_CLEARTID: usize = 0x20_0000;
+/// 将新线程 ID 写入新线程的 `ctid`
+const CLONE_CHILD_SETTID: usize = 0x100_0000;
+
+/// `wait4` 中没有结束的子进程时立即返回
+const WNOHANG: usize = 1;
+
+/// Linux 系统调用的分发表
+pub(super) fn linux_syscall(
+    context: &mut Context,
+    syscall_id: usize,
+    args: [usize; 6],
+) -> KernelResult<SyscallResult> {
+    match syscall_id {
+        SYS_IOCTL => Err(KernelError::NotTerminal),
+        SYS_UMOUNT2 => sys_umount(args[0]),
+        SYS_MOUNT => sys_mount(args[0], args[1], args[2]),
+        SYS_OPENAT => sys_openat(args[0], args[1]),
+        SYS_CLOSE => sys_close(args[0]),
+        SYS_READ => sys_linux_read(args[0], args[1], args[2]),
+        SYS_WRITE => sys_write(args[0], args[1], args[2]),
+        SYS_READV => sys_readv(args[0], args[1], args[2]),
+        SYS_WRITEV => sys_writev(args[0], args[1], args[2]),
+        // Linux 的 sync 总是成功
+        SYS_SYNC => {
+            let _ = sys_sync();
+            Ok(SyscallResult::Proceed(0))
+        }
+        SYS_FSYNC => sys_fsync(args[0]),
+        SYS_EXIT => sys_linux_exit(args[0]),
+        SYS_EXIT_GROUP => sys_exit_group(args[0]),
+        SYS_SET_TID_ADDRESS => sys_set_tid_address(args[0]),
+        SYS_SCHED_YIELD => Ok(SyscallResult::Park(0)),
+        // 信号尚未实现，设置处理函数和屏蔽字时直接返回成功
+        SYS_RT_SIGACTION | SYS_RT_SIGPROCMASK => Ok(SyscallResult::Proceed(0)),
+        SYS_GETPID => sys_getpid(),
+        SYS_GETPPID => sys_getppid(),
+        // 只有 root 一个用户
+        SYS_GETUID | SYS_GETEUID | SYS_GETGID | SYS_GETEGID => Ok(SyscallResult::Proceed(0)),
+        SYS_GETTID => sys_get_tid(),
+        SYS_BRK => sys_brk(args[0]),
+        SYS_MUNMAP => sys_munmap(args[0], args[1]),
+        SYS_CLONE => sys_clone(context, args[0], args[1], args[2], args[3], args[4]),
+        SYS_EXECVE => sys_execve(context, args[0], args[1], args[2]),
+        SYS_MMAP => sys_mmap(args[0], args[1], args[2], args[3], args[4], args[5]),
+        SYS_MPROTECT => sys_mprotect(args[0], args[1], args[2]),
+        SYS_WAIT4 => sys_wait4(args[0] as isize, args[1], args[2]),
+        _ => Err(KernelError::Unsupported),
+    }
+}
+
+/// 打开文件，路径相对于根目录；不支持 `O_CREAT` 等创建文件的标志
+fn sys_openat(dirfd: usize, path: usize) -> KernelResult<SyscallResult> {
+    let process = PROCESSOR.lock().current_thread().process.clone();
+    let path = read_path(&mut process.inner().memory_set, path)?;
+    // 还没有当前目录和目录的文件描述符，相对路径只能相对于根目录
+    if !path.starts_with('/') && dirfd as isize != AT_FDCWD {
+        return Err(KernelError::BadDescriptor);
+    }
+    let inode = vfs::lookup(&path)?;
+    let fd = process.inner().add_file(vfs::FileHandle::new(inode));
+    Ok(SyscallResult::Proceed(fd as isize))
+}
+
+/// 关闭文件描述符
+fn sys_close(fd: usize) -> KernelResult<SyscallResult> {
+    let process = PROCESSOR.lock().current_thread().process.clone();
+    if process.inner().close_file(fd) {
+        Ok(SyscallResult::Proceed(0))
+    } else {
+        Err(KernelError::BadDescriptor)
+    }
+}
+
+/// 读取文件，没有数据时阻塞到有数据为止，读到文件结尾时返回 0
+///
+/// 与 [`sys_read`] 相同，一次最多读取 [`IO_BUFFER_SIZE`] 个字节
+fn sys_linux_read(fd: usize, buffer: usize, size: usize) -> KernelResult<SyscallResult> {
+    let thread = PROCESSOR.lock().current_thread();
+    let process = thread.process.clone();
+    let size = size.min(IO_BUFFER_SIZE);
+    let buffer = UserSlice::new(buffer, size);
+    buffer
+        .check_writable(&mut process.inner().memory_set)
+        .map_err(|_| KernelError::Fault)?;
+    let file = process.inner().file(fd).ok_or(KernelError::BadDescriptor)?;
+    let mut data = vec![0; size];
+    let count = file.read(&mut data)?;
+    // 没有数据时文件会令线程休眠，唤醒后重新读取
+    if count == 0 && size > 0 && thread.inner().sleeping {
+        return Ok(SyscallResult::Restart);
+    }
+    buffer
+        .write(&mut process.inner().memory_set, &data[..count])
+        .map_err(|_| KernelError::Fault)?;
+    Ok(SyscallResult::Proceed(count as isize))
+}
+
+/// 读出用户的 `iovec` 数组，每项为起始地址和长度
+fn read_iovecs(memory_set: &mut MemorySet, iov: usize, count: usize) -> KernelResult<Vec<UserSlice>> {
+    if count > IOV_MAX {
+        return Err(KernelError::InvalidArgument);
+    }
+    // 数组的结尾不能越过地址空间，此后计算各项的地址不会溢出
+    iov.checked_add(count * 2 * core::mem::size_of::<usize>())
+        .ok_or(KernelError::Fault)?;
+    (0..count)
+        .map(|i| {
+            let entry = iov + i * 2 * core::mem::size_of::<usize>();
+            let base = UserPtr::<usize>::new(entry).read(memory_set);
+            let len = UserPtr::<usize>::new(entry + core::mem::size_of::<usize>()).read(memory_set);
+            match (base, len) {
+                (Ok(base), Ok(len)) => Ok(UserSlice::new(base, len)),
+                _ => Err(KernelError::Fault),
+            }
+        })
+        .collect()
+}
+
+/// 依次读入多个缓冲区，返回读取的总字节数
+///
+/// 已经读到数据时，后面的缓冲区出错或需要等待则返回已经读到的部分
+fn sys_readv(fd: usize, iov: usize, count: usize) -> KernelResult<SyscallResult> {
+    let process = PROCESSOR.lock().current_thread().process.clone();
+    let buffers = read_iovecs(&mut process.inner().memory_set, iov, count)?;
+    let mut total = 0;
+    for buffer in buffers.iter().filter(|buffer| !buffer.is_empty()) {
+        match sys_linux_read(fd, buffer.address(), buffer.len()) {
+            Ok(SyscallResult::Proceed(count)) => {
+                total += count;
+                // 读到的数据不足时不再读后面的缓冲区
+                if (count as usize) < buffer.len() {
+                    break;
+                }
+            }
+            result if total == 0 => return result,
+            _ => break,
+        }
+    }
+    Ok(SyscallResult::Proceed(total))
+}
+
+/// 依次写出多个缓冲区，返回写入的总字节数
+fn sys_writev(fd: usize, iov: usize, count: usize) -> KernelResult<SyscallResult> {
+    let process = PROCESSOR.lock().current_thread().process.clone();
+    let buffers = read_iovecs(&mut process.inner().memory_set, iov, count)?;
+    let mut total = 0;
+    for buffer in buffers.iter().filter(|buffer| !buffer.is_empty()) {
+        match sys_write(fd, buffer.address(), buffer.len()) {
+            Ok(SyscallResult::Proceed(count)) => total += count,
+            result if total == 0 => return result,
+            _ => break,
+        }
+    }
+    Ok(SyscallResult::Proceed(total))
+}
+
+/// 线程结束时按照 `set_tid_address` 将用户地址处的线程 ID 清零
+fn clear_child_tid(thread: &Thread) {
+    let address = thread.inner().clear_child_tid;
+    if address != 0 {
+        let memory_set = &mut thread.process.inner().memory_set;
+        // 地址不合法时忽略，线程无论如何都会结束
+        let _ = UserPtr::<u32>::new(address).write(memory_set, 0);
+    }
+}
+
+/// 结束当前线程，进程中的最后一个线程结束时进程随之结束
+fn sys_linux_exit(code: usize) -> KernelResult<SyscallResult> {
+    let thread = PROCESSOR.lock().current_thread();
+    clear_child_tid(&thread);
+    thread.process.exit_thread(&thread, code as i32 & 0xff);
+    Ok(SyscallResult::Kill)
+}
+
+/// 结束当前进程中的所有线程
+fn sys_exit_group(code: usize) -> KernelResult<SyscallResult> {
+    let thread = PROCESSOR.lock().current_thread();
+    clear_child_tid(&thread);
+    thread.process.exit(code as i32 & 0xff);
+    Ok(SyscallResult::Kill)
+}
+
+/// 记录线程结束时需要清零的地址，返回线程 ID
+fn sys_set_tid_address(address: usize) -> KernelResult<SyscallResult> {
+    let thread = PROCESSOR.lock().current_thread();
+    thread.inner().clear_child_tid = address;
+    Ok(SyscallResult::Proceed(thread.id))
+}
+
+/// 当前进程的 ID
+pub(super) fn sys_getpid() -> KernelResult<SyscallResult> {
+    let process = PROCESSOR.lock().current_thread().process.clone();
+    Ok(SyscallResult::Proceed(process.pid as isize))
+}
+
+/// 父进程的 ID，父进程已经结束时为 init，没有 init 时返回 0
+pub(super) fn sys_getppid() -> KernelResult<SyscallResult> {
+    let process = PROCESSOR.lock().current_thread().process.clone();
+    let parent = process.inner().parent.upgrade();
+    Ok(SyscallResult::Proceed(parent.map_or(0, |parent| parent.pid as isize)))
+}
+
+/// 创建线程或子进程
+///
+/// 带有 `CLONE_THREAD` 时在当前进程中创建线程，使用用户提供的栈；
+/// 否则复制出子进程，`stack` 不为 0 时子进程从这个栈开始执行。
+/// 父进程得到新线程的线程 ID 或子进程的进程 ID，新线程得到 0
+pub(super) fn sys_clone(
+    context: &Context,
+    flags: usize,
+    stack: usize,
+    ptid: usize,
+    tls: usize,
+    ctid: usize,
+) -> KernelResult<SyscallResult> {
+    let current = PROCESSOR.lock().current_thread();
+    let priority = current.inner().priority;
+    // 新线程从 ecall 的下一条指令开始执行，返回值为 0
+    let mut child_context = *context;
+    child_context.x[10] = 0;
+    if stack != 0 {
+        child_context.set_sp(stack);
+    }
+    if flags & CLONE_SETTLS != 0 {
+        child_context.x[4] = tls;
+    }
+    let (thread, id) = if flags & CLONE_THREAD != 0 {
+        if flags & CLONE_VM == 0 || stack == 0 {
+            return Err(KernelError::InvalidArgument);
+        }
+        let stack = VirtualAddress(stack);
+        let thread = Thread::from_context(
+            current.process.clone(),
+            Range::from(stack..stack),
+            child_context,
+            priority,
+        );
+        let id = thread.id;
+        (thread, id)
+    } else {
+        // 没有 CLONE_THREAD 时总是复制地址空间，CLONE_VM（例如 vfork）也按复制处理
+        let child = current.process.fork()?;
+        let stack = current.inner().stack;
+        let thread = Thread::from_context(child.clone(), stack, child_context, priority);
+        (thread, child.pid as isize)
+    };
+    // 新线程已经创建，写入 ID 的地址不合法时忽略
+    let id_bytes = (id as u32).to_ne_bytes();
+    if flags & CLONE_PARENT_SETTID != 0 {
+        let _ = write_u32(&mut current.process.inner().memory_set, ptid, &id_bytes);
+    }
+    if flags & CLONE_CHILD_SETTID != 0 {
+        let _ = write_u32(&mut thread.process.inner().memory_set, ctid, &id_bytes);
+    }
+    if flags & CLONE_CHILD_CLEARTID != 0 {
+        thread.inner().clear_child_tid = ctid;
+    }
+    PROCESSOR.lock().add_thread(thread);
+    Ok(SyscallResult::Proceed(id))
+}
+
+/// 写入任意地址空间中的一个 `u32`，地址需要对用户可写
+fn write_u32(memory_set: &mut MemorySet, address: usize, bytes: &[u8; 4]) -> MemoryResult<()> {
+    user::check_user(memory_set, address, bytes.len(), Flags::WRITABLE)?;
+    memory_set.write_bytes(VirtualAddress(address), bytes)
+}
+
+/// 读出以空指针结尾的字符串指针数组
+///
+/// 读出的字符串占用的字节数从 `remaining` 中扣除，超过时返回 `ArgumentListTooLong`
+fn read_c_str_array(
+    memory_set: &mut MemorySet,
+    address: usize,
+    remaining: &mut usize,
+) -> KernelResult<Vec<String>> {
+    let mut strings = Vec::new();
+    if address == 0 {
+        return Ok(strings);
+    }
+    loop {
+        if strings.len() >= ARG_MAX {
+            return Err(KernelError::ArgumentListTooLong);
+        }
+        let pointer = address
+            .checked_add(strings.len() * core::mem::size_of::<usize>())
+            .ok_or(KernelError::Fault)?;
+        let string = UserPtr::<usize>::new(pointer)
+            .read(memory_set)
+            .map_err(|_| KernelError::Fault)?;
+        if string == 0 {
+            return Ok(strings);
+        }
+        let string = read_path(memory_set, string)?;
+        let size = string.len() + 1 + core::mem::size_of::<usize>();
+        if size > *remaining {
+            return Err(KernelError::ArgumentListTooLong);
+        }
+        *remaining -= size;
+        strings.push(string);
+    }
+}
+
+/// 在当前进程中执行新的程序
+///
+/// 加载成功之后才替换当前进程的地址空间，此前出错时返回错误码，当前程序继续执行
+pub(super) fn sys_execve(
+    context: &mut Context,
+    path: usize,
+    argv: usize,
+    envp: usize,
+) -> KernelResult<SyscallResult> {
+    let thread = PROCESSOR.lock().current_thread();
+    let process = thread.process.clone();
+    let (path, args, envs) = {
+        let memory_set = &mut process.inner().memory_set;
+        // 参数和环境变量共用同一个上限
+        let mut remaining = ARG_BYTES_MAX;
+        (
+            read_path(memory_set, path)?,
+            read_c_str_array(memory_set, argv, &mut remaining)?,
+            read_c_str_array(memory_set, envp, &mut remaining)?,
+        )
+    };
+    // 与创建用户进程时相同：只读取 ELF 头和程序头，再加载到新的地址空间
+    let inode = vfs::lookup(&path)?;
+    let headers = read_headers(&inode)?;
+    let elf = ElfFile::new(headers.as_slice()).map_err(|_| KernelError::NotExecutable)?;
+    let (memory_set, info) = MemorySet::from_elf(&inode, &elf, true)?;
+    let init_info = InitInfo::new(args, envs, &elf, &info);
+    process.exec(&thread, memory_set, &info);
+    // 此后原先的程序已经不存在，出错时只能结束进程
+    match start_program(&thread, &init_info) {
+        Ok(sp) => {
+            *context = Context::new(sp.into(), info.start.into(), None, true);
+            Ok(SyscallResult::Proceed(0))
+        }
+        Err(error) => {
+            println!("process {} killed in execve: {}", process.pid, error);
+            sys_exit_group(KernelError::from(error).errno() as usize)
+        }
+    }
+}
+
+/// 为 `execve` 之后的线程分配新的栈并放置参数，返回栈顶
+fn start_program(thread: &Thread, init_info: &InitInfo) -> MemoryResult<VirtualAddress> {
+    let process = &thread.process;
+    let stack_flags = process.inner().stack_flags;
+    let stack = process.alloc_page_range(STACK_SIZE, stack_flags)?;
+    thread.inner().stack = stack;
+    init_info.push_at(&process.inner().memory_set, stack.end, STACK_SIZE)
+}
+
+/// 等待子进程结束并回收，返回其进程 ID，退出码按 Linux 的格式写入 `wstatus`
+///
+/// `pid` 为 -1 时等待任意子进程；还没有进程组，`pid` 为 0 或小于 -1 时也等待任意子进程
+pub(super) fn sys_wait4(pid: isize, wstatus: usize, options: usize) -> KernelResult<SyscallResult> {
+    let process = PROCESSOR.lock().current_thread().process.clone();
+    let pid = if pid > 0 { Some(pid as ProcessID) } else { None };
+    match process.wait_child(pid)? {
+        Some((pid, code)) => {
+            if wstatus != 0 {
+                let status = (code & 0xff) << 8;
+                UserPtr::<i32>::new(wstatus)
+                    .write(&mut process.inner().memory_set, status)
+                    .map_err(|_| KernelError::Fault)?;
+            }
+            Ok(SyscallResult::Proceed(pid as isize))
+        }
+        None if options & WNOHANG != 0 => Ok(SyscallResult::Proceed(0)),
+        // 等待子进程结束时唤醒，然后重新检查
+        None => {
+            process.child_exit.wait();
+            Ok(SyscallResult::Restart)
+        }
+    }
+}
@@ -0,0 +1,128 @@
+//! 内存相关的内核功能
+
+use super::*;
+use crate::memory::{Flags, Range, VirtualAddress, PAGE_SIZE};
+
+/// 页面可读
+const PROT_READ: usize = 1;
+/// 页面可写
+const PROT_WRITE: usize = 2;
+/// 页面可执行
+const PROT_EXEC: usize = 4;
+
+/// 共享映射，对文件映射的修改会写回文件
+const MAP_SHARED: usize = 0x01;
+/// 私有映射，修改只对当前进程可见
+const MAP_PRIVATE: usize = 0x02;
+/// 必须映射在给定的地址
+const MAP_FIXED: usize = 0x10;
+/// 匿名映射，不对应任何文件
+const MAP_ANONYMOUS: usize = 0x20;
+
+/// 将 `PROT_*` 转换为页表项的权限
+///
+/// RISC-V 不允许可写而不可读的页面，所以可写的页面总是可读。
+/// 不支持 `PROT_NONE`：RWX 全为 0 的有效页表项会被硬件当作指向下一级页表
+fn prot_to_flags(prot: usize) -> KernelResult<Flags> {
+    if prot & (PROT_READ | PROT_WRITE | PROT_EXEC) == 0 {
+        return Err(KernelError::InvalidArgument);
+    }
+    Ok(Flags::readable(prot & (PROT_READ | PROT_WRITE) != 0)
+        | Flags::writable(prot & PROT_WRITE != 0)
+        | Flags::executable(prot & PROT_EXEC != 0))
+}
+
+/// 在当前进程中映射一段内存
+///
+/// 参数与 Linux 相同。成功返回映射的起始地址
+pub(super) fn sys_mmap(
+    address: usize,
+    length: usize,
+    prot: usize,
+    flags: usize,
+    fd: usize,
+    offset: usize,
+) -> KernelResult<SyscallResult> {
+    let process = PROCESSOR.lock().current_thread().process.clone();
+    let shared = match flags & (MAP_SHARED | MAP_PRIVATE) {
+        MAP_SHARED => true,
+        MAP_PRIVATE => false,
+        _ => return Err(KernelError::InvalidArgument),
+    };
+    let source = if flags & MAP_ANONYMOUS != 0 {
+        MmapSource::Anonymous
+    } else {
+        if offset % PAGE_SIZE != 0 {
+            return Err(KernelError::InvalidArgument);
+        }
+        let inode = process.inner().file(fd).ok_or(KernelError::BadDescriptor)?.inode.clone();
+        if shared {
+            MmapSource::SharedFile(inode, offset)
+        } else {
+            MmapSource::PrivateFile(inode, offset)
+        }
+    };
+    let range = process.mmap(
+        VirtualAddress(address),
+        length,
+        prot_to_flags(prot)?,
+        flags & MAP_FIXED != 0,
+        source,
+    )?;
+    Ok(SyscallResult::Proceed(range.start.0 as isize))
+}
+
+/// 解除当前进程中一段内存的映射
+///
+/// 成功返回 0
+pub(super) fn sys_munmap(address: usize, length: usize) -> KernelResult<SyscallResult> {
+    let process = PROCESSOR.lock().current_thread().process.clone();
+    let range = Range::from(VirtualAddress(address)..VirtualAddress(address.saturating_add(length)));
+    process.munmap(range)?;
+    Ok(SyscallResult::Proceed(0))
+}
+
+/// 创建共享内存，由当前进程持有直到进程结束
+///
+/// 成功返回共享内存的 ID
+pub(super) fn sys_shm_create(size: usize) -> KernelResult<SyscallResult> {
+    let process = PROCESSOR.lock().current_thread().process.clone();
+    let id = process.shm_create(size)?;
+    Ok(SyscallResult::Proceed(id as isize))
+}
+
+/// 将共享内存映射到当前进程中，`address` 为 0 时由内核选择地址
+///
+/// 成功返回映射的起始地址
+pub(super) fn sys_shm_attach(id: usize, address: usize, prot: usize) -> KernelResult<SyscallResult> {
+    let process = PROCESSOR.lock().current_thread().process.clone();
+    let range = process.shm_attach(id, VirtualAddress(address), prot_to_flags(prot)?)?;
+    Ok(SyscallResult::Proceed(range.start.0 as isize))
+}
+
+/// 解除当前进程中从 `address` 开始的共享内存映射
+///
+/// 成功返回 0
+pub(super) fn sys_shm_detach(address: usize) -> KernelResult<SyscallResult> {
+    let process = PROCESSOR.lock().current_thread().process.clone();
+    process.shm_detach(VirtualAddress(address))?;
+    Ok(SyscallResult::Proceed(0))
+}
+
+/// 调整当前进程的程序断点
+///
+/// 与 Linux 相同，总是返回调整后的断点，`address` 为 0 时用于查询当前的断点
+pub(super) fn sys_brk(address: usize) -> KernelResult<SyscallResult> {
+    let process = PROCESSOR.lock().current_thread().process.clone();
+    Ok(SyscallResult::Proceed(process.brk(VirtualAddress(address)).0 as isize))
+}
+
+/// 修改当前进程中一段内存的权限
+///
+/// 成功返回 0
+pub(super) fn sys_mprotect(address: usize, length: usize, prot: usize) -> KernelResult<SyscallResult> {
+    let process = PROCESSOR.lock().current_thread().process.clone();
+    let range = Range::from(VirtualAddress(address)..VirtualAddress(address.saturating_add(length)));
+    process.mprotect(range, prot_to_flags(prot)?)?;
+    Ok(SyscallResult::Proceed(0))
+}
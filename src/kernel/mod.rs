@@ -2,13 +2,17 @@
 
 mod condvar;
 mod fs;
+mod linux;
+mod memory;
 mod process;
 mod syscall;
 
+use crate::error::{KernelError, KernelResult};
 use crate::interrupt::*;
 use crate::process::*;
 use alloc::sync::Arc;
 pub(self) use fs::*;
+pub(self) use memory::*;
 pub(self) use process::*;
 use spin::Mutex;
 pub(self) use syscall::*;
@@ -2,28 +2,27 @@
 
 use super::*;
 
-pub(super) fn sys_exit(code: usize) -> SyscallResult {
-    println!(
-        "thread {} exit with code {}",
-        PROCESSOR.lock().current_thread().id,
-        code
-    );
-    SyscallResult::Kill
+/// 结束当前线程，进程中的最后一个线程结束时进程以 `code` 结束
+pub(super) fn sys_exit(code: usize) -> KernelResult<SyscallResult> {
+    let thread = PROCESSOR.lock().current_thread();
+    println!("thread {} exit with code {}", thread.id, code as isize);
+    thread.process.exit_thread(&thread, code as i32);
+    Ok(SyscallResult::Kill)
 }
 
 // 获得线程ID
-pub(super) fn sys_get_tid() -> SyscallResult {
-    SyscallResult::Proceed(PROCESSOR.lock().current_thread().id.clone())
+pub(super) fn sys_get_tid() -> KernelResult<SyscallResult> {
+    Ok(SyscallResult::Proceed(PROCESSOR.lock().current_thread().id))
 }
 
 // sys_fork 系统调用，使得该系统调用为父线程返回自身的线程 ID，而为子线程返回 0。
 // fork 子进程
-pub(super) fn sys_fork(context: &Context) -> SyscallResult {
+pub(super) fn sys_fork(context: &Context) -> KernelResult<SyscallResult> {
     let id = PROCESSOR.lock().current_thread().id.clone();
-    PROCESSOR.lock().fork_current_thread(context);
+    PROCESSOR.lock().fork_current_thread(context)?;
     if PROCESSOR.lock().current_thread().id.clone() == id {
-        SyscallResult::Proceed(id)
+        Ok(SyscallResult::Proceed(id))
     } else {
-        SyscallResult::Proceed(0)
+        Ok(SyscallResult::Proceed(0))
     }
 }
@@ -1,13 +1,33 @@
 //! 实现各种系统调用
 
 use super::*;
+use super::linux::{
+    linux_syscall, sys_clone, sys_execve, sys_getpid, sys_getppid, sys_wait4,
+};
 
+pub const SYS_UMOUNT: usize = 39;
+pub const SYS_MOUNT: usize = 40;
 pub const SYS_READ: usize = 63;
 pub const SYS_WRITE: usize = 64;
 pub const SYS_OPEN: usize = 65;
+pub const SYS_SYNC: usize = 81;
+pub const SYS_FSYNC: usize = 82;
 pub const SYS_EXIT: usize = 93;
 pub const SYS_GETTID: usize = 94; // 用户线程可以获取自身的线程 ID
 pub const SYS_FORK: usize = 95;
+pub const SYS_SCHED_YIELD: usize = 124;
+pub const SYS_GETPID: usize = 172;
+pub const SYS_GETPPID: usize = 173;
+pub const SYS_SHM_CREATE: usize = 194;
+pub const SYS_SHM_ATTACH: usize = 196;
+pub const SYS_SHM_DETACH: usize = 197;
+pub const SYS_BRK: usize = 214;
+pub const SYS_MUNMAP: usize = 215;
+pub const SYS_CLONE: usize = 220;
+pub const SYS_EXECVE: usize = 221;
+pub const SYS_MMAP: usize = 222;
+pub const SYS_MPROTECT: usize = 226;
+pub const SYS_WAIT4: usize = 260;
 
 /// 系统调用在内核之内的返回值
 pub(super) enum SyscallResult {
@@ -17,6 +37,8 @@ pub(super) enum SyscallResult {
     Park(isize),
     /// 丢弃当前 context，调度下一个线程继续执行
     Kill,
+    /// 当前线程已经休眠，唤醒后重新执行这个系统调用
+    Restart,
 }
 
 /// 系统调用的总入口
@@ -25,21 +47,57 @@ pub fn syscall_handler(context: &mut Context) -> *mut Context {
     context.sepc += 4;
 
     let syscall_id = context.x[17];
-    let args = [context.x[10], context.x[11], context.x[12]];
+    let args = [
+        context.x[10],
+        context.x[11],
+        context.x[12],
+        context.x[13],
+        context.x[14],
+        context.x[15],
+    ];
+
+    // 按照进程使用的接口分发
+    let abi = PROCESSOR.lock().current_thread().process.abi;
+    if abi == SyscallAbi::Linux {
+        let result = linux_syscall(context, syscall_id, args);
+        return finish(context, result);
+    }
 
     let result = match syscall_id {
-        SYS_READ => sys_read(args[0], args[1] as *mut u8, args[2]),
-        SYS_WRITE => sys_write(args[0], args[1] as *mut u8, args[2]),
+        SYS_READ => sys_read(args[0], args[1], args[2]),
+        SYS_WRITE => sys_write(args[0], args[1], args[2]),
         SYS_EXIT => sys_exit(args[0]),
         SYS_GETTID => sys_get_tid(),
         SYS_FORK => sys_fork(context),
-        SYS_OPEN => sys_open(args[1] as *mut u8, args[2]),
-        _ => {
-            println!("unimplemented syscall: {}", syscall_id);
-            SyscallResult::Kill
-        }
+        // 创建子进程、执行程序和回收子进程与 Linux 的接口相同
+        SYS_CLONE => sys_clone(context, args[0], args[1], args[2], args[3], args[4]),
+        SYS_EXECVE => sys_execve(context, args[0], args[1], args[2]),
+        SYS_WAIT4 => sys_wait4(args[0] as isize, args[1], args[2]),
+        SYS_GETPID => sys_getpid(),
+        SYS_GETPPID => sys_getppid(),
+        SYS_SCHED_YIELD => Ok(SyscallResult::Park(0)),
+        SYS_OPEN => sys_open(args[1], args[2]),
+        SYS_MOUNT => sys_mount(args[0], args[1], args[2]),
+        SYS_UMOUNT => sys_umount(args[0]),
+        SYS_SYNC => sys_sync(),
+        SYS_FSYNC => sys_fsync(args[0]),
+        SYS_SHM_CREATE => sys_shm_create(args[0]),
+        SYS_SHM_ATTACH => sys_shm_attach(args[0], args[1], args[2]),
+        SYS_SHM_DETACH => sys_shm_detach(args[0]),
+        SYS_BRK => sys_brk(args[0]),
+        SYS_MMAP => sys_mmap(args[0], args[1], args[2], args[3], args[4], args[5]),
+        SYS_MUNMAP => sys_munmap(args[0], args[1]),
+        SYS_MPROTECT => sys_mprotect(args[0], args[1], args[2]),
+        _ => Err(KernelError::Unsupported),
     };
+    finish(context, result)
+}
 
+/// 按照系统调用的结果写入返回值，或是切换到下一个线程
+///
+/// 所有系统调用的错误都在这里转换为负的错误码
+fn finish(context: &mut Context, result: KernelResult<SyscallResult>) -> *mut Context {
+    let result = result.unwrap_or_else(|error| SyscallResult::Proceed(error.errno()));
     match result {
         SyscallResult::Proceed(ret) => {
             // 将返回值放入 context 中
@@ -58,5 +116,11 @@ pub fn syscall_handler(context: &mut Context) -> *mut Context {
             PROCESSOR.lock().kill_current_thread();
             PROCESSOR.lock().prepare_next_thread()
         }
+        SyscallResult::Restart => {
+            // 回到 ecall 指令，参数寄存器保持不变
+            context.sepc -= 4;
+            PROCESSOR.lock().park_current_thread(context);
+            PROCESSOR.lock().prepare_next_thread()
+        }
     }
 }
@@ -31,6 +31,7 @@
 
 #[macro_use]
 mod console;
+mod error;
 mod panic;
 mod sbi;
 mod interrupt;
@@ -43,9 +44,14 @@ mod kernel;
 extern crate alloc;
 
 use process::*;
-use alloc::sync::Arc;
-use memory::PhysicalAddress;
-use fs::{INodeExt, ROOT_INODE};
+use alloc::{
+    string::{String, ToString},
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
+use memory::{ElfError, PhysicalAddress};
+use fs::INodeExt;
 use xmas_elf::ElfFile;
 
 // 汇编编写的程序入口，具体见该文件 entry.asm
@@ -272,27 +278,29 @@ pub fn create_kernel_thread(
     thread
 }
 
-/// 创建一个用户进程，从指定的文件名读取 ELF
-pub fn create_user_process(name: &str, priority: usize) -> Arc<Thread> {
+/// 创建一个用户进程，从指定的文件名读取 ELF，参数和环境变量放在初始栈上
+///
+/// `abi` 为进程使用的系统调用接口，Linux 程序使用 [`SyscallAbi::Linux`]；
+/// `pid` 为进程 ID，通常由 [`Process::alloc_pid`] 分配
+pub fn create_user_process(
+    name: &str,
+    args: Vec<String>,
+    envs: Vec<String>,
+    abi: SyscallAbi,
+    pid: ProcessID,
+    priority: usize,
+) -> Result<Arc<Thread>, ElfError> {
     // 从文件系统中找到程序
-    let app = ROOT_INODE.find(name).unwrap();
-    // 读取数据
-    let data = app.readall().unwrap();
+    let app = fs::lookup(name)?;
+    // 只读取 ELF 头和程序头，段的内容在加载时或第一次访问时读取
+    let headers = memory::elf::read_headers(&app)?;
     // 解析 ELF 文件
-    let elf = ElfFile::new(data.as_slice()).unwrap();
+    let elf = ElfFile::new(headers.as_slice()).map_err(ElfError::Malformed)?;
     // 利用 ELF 文件创建进程，映射空间并加载数据
-    let process = Process::from_elf(&elf, true).unwrap();
-    // 再从 ELF 中读出程序入口地址，创建该进程的线程
-    Thread::new(process, elf.header.pt2.entry_point() as usize, None, priority).unwrap()
-}
-
-fn sample_process(message: usize) {
-    println!("hello from kernel thread {}", message);
-    // for i in 0..4000000{
-    //     if i%1000000 == 0 {
-    //         println!("Hello world from kernel id {} program!{}", message, i);
-    //     }
-    // }
+    let (process, info) = Process::from_elf(&app, &elf, true, abi, pid)?;
+    // 从加载信息中取得开始执行的地址（动态链接时为解释器的入口），创建该进程的线程
+    let init_info = InitInfo::new(args, envs, &elf, &info);
+    Ok(Thread::new_user(process, info.start.into(), &init_info, priority)?)
 }
 
 /// 测试任何内核线程都可以操作文件系统和驱动
@@ -314,10 +322,23 @@ fn add_kernel_thread(kernel_process: Arc<Process>, entry_point: usize, arguments
         .add_thread(create_kernel_thread(kernel_process, entry_point, arguments, priority));
 }
 
-// 向处理机添加一个用户进程参与调度
-fn add_user_thread(name: &str, priority: usize) {
-    let thread = create_user_process(name, priority);
-    PROCESSOR.lock().add_thread(thread);
+/// 没有在启动参数中用 `init=` 指定时 init 程序的路径
+const DEFAULT_INIT_PATH: &str = "/init";
+
+/// 启动第一个用户进程 init，它的进程 ID 为 [`INIT_PID`]
+///
+/// 由 init 按照配置文件启动其他程序，并回收成为孤儿的进程
+fn start_init() {
+    let bootargs = drivers::device_tree::bootargs();
+    let path = bootargs
+        .split_whitespace()
+        .find_map(|arg| arg.strip_prefix("init="))
+        .unwrap_or(DEFAULT_INIT_PATH);
+    let args = vec![path.to_string()];
+    match create_user_process(path, args, Vec::new(), SyscallAbi::Native, INIT_PID, 8) {
+        Ok(thread) => PROCESSOR.lock().add_thread(thread),
+        Err(error) => panic!("failed to start init {}: {:?}", path, error),
+    }
 }
 
 // 开始运行处理机
@@ -349,17 +370,10 @@ pub extern "C" fn rust_main(_hart_id: usize, dtb_pa: PhysicalAddress) -> ! { //
     println!("Finish initialization!");
 
     let kernel_process = Process::new_kernel().unwrap();
-    for i in 1..9usize {
-        add_kernel_thread(kernel_process.clone(), sample_process as usize, Some(&[i]), i);
-        // 如果采用Stride Scheduling 算法，可以看到线程9最先退出，之后是线程8，线程7....
-        // 而其他算法不考虑优先级，所以退出顺序不定.
-    }
-    // add_user_thread("hello_world", 1);
-    // add_user_thread("hello_world", 2);
-    // add_user_thread("hello_world", 4);
-    // add_user_thread("hello_world", 8);
-    add_user_thread("fantastic_text", 8);
-    add_user_thread("user_shell", 8);
+    // 定期将文件系统写回设备
+    add_kernel_thread(kernel_process, fs::flusher as usize, None, 1);
+    // 其他用户程序都由 init 启动
+    start_init();
 
     start_processor();
     unreachable!()
@@ -18,6 +18,15 @@ pub const DEVICE_START_ADDRESS: PhysicalAddress = PhysicalAddress(0x1000_0000);
 /// MMIO 设备段内存区域结束地址
 pub const DEVICE_END_ADDRESS: PhysicalAddress = PhysicalAddress(0x1001_0000);
 
+/// 用户地址空间的结束地址，即 Sv39 中虚拟地址的低半部分
+pub const USER_END_ADDRESS: VirtualAddress = VirtualAddress(0x40_0000_0000);
+
+/// 位置无关的可执行文件的加载地址
+pub const ELF_DYN_BASE: VirtualAddress = VirtualAddress(0x10_0000_0000);
+
+/// 动态链接程序的解释器的加载地址
+pub const INTERP_BASE: VirtualAddress = VirtualAddress(0x20_0000_0000);
+
 // 我们直接将 DRAM 物理内存结束地址硬编码到内核中，
 // 同时因为我们操作系统本身也用了一部分空间，我们也记录下操作系统用到的地址结尾（即 linker script 中的 kernel_end）。
 lazy_static! { // lazy_static! 宏帮助我们在第一次使用 lazy_static! 宏包裹的变量时自动完成这些求值工作。
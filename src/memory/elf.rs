@@ -0,0 +1,126 @@
+//! 加载 ELF 文件的错误 [`ElfError`] 和结果 [`ElfInfo`]
+//!
+//! 加载的过程见 [`MemorySet::from_elf`](crate::memory::MemorySet::from_elf)。
+//! 动态链接的程序由 `PT_INTERP` 指定的解释器（例如 musl 的 `ld.so`）完成链接，
+//! 内核只负责将程序和解释器都映射到地址空间中，并从解释器的入口开始执行。
+//!
+//! 加载时不会读入整个文件：只读取 ELF 头和程序头，可写的段在加载时读入，
+//! 只读的段则在第一次访问时才从页缓存映射。
+
+use crate::fs::{is_cacheable, INode, PAGE_CACHE};
+use crate::memory::{address::*, config::PAGE_SIZE};
+use alloc::{sync::Arc, vec, vec::Vec};
+use core::cmp::min;
+use rcore_fs::vfs::FsError;
+use xmas_elf::{
+    header::{Class, Data},
+    ElfFile,
+};
+
+/// RISC-V 的 `e_machine`
+const EM_RISCV: u16 = 243;
+
+/// `PT_GNU_STACK`，其权限表示栈是否可执行
+pub const PT_GNU_STACK: u32 = 0x6474_e551;
+
+/// 加载 ELF 文件时出现的错误
+#[derive(Debug)]
+pub enum ElfError {
+    /// 读取文件失败
+    Io(FsError),
+    /// 无法解析的 ELF 文件
+    Malformed(&'static str),
+    /// 不是 64 位小端的 RISC-V 文件
+    UnsupportedArch,
+    /// 既不是可执行文件，也不是位置无关的可执行文件
+    UnsupportedType,
+    /// 段的大小、偏移或地址不合法，或者段之间相互重叠
+    InvalidSegment,
+    /// 入口地址不在可执行的段中
+    InvalidEntry,
+    /// `PT_INTERP` 指定的解释器不是位置无关的，或者自身也需要解释器
+    InvalidInterpreter,
+    /// 建立映射时出错
+    Memory(&'static str),
+}
+
+impl From<FsError> for ElfError {
+    fn from(error: FsError) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl From<&'static str> for ElfError {
+    fn from(error: &'static str) -> Self {
+        Self::Memory(error)
+    }
+}
+
+/// 加载之后的程序信息，用于创建线程和构建初始栈
+#[derive(Clone, Copy, Debug)]
+pub struct ElfInfo {
+    /// 程序的入口地址，已经加上加载偏移
+    pub entry: VirtualAddress,
+    /// 线程开始执行的地址，有解释器时为解释器的入口，否则与 `entry` 相同
+    pub start: VirtualAddress,
+    /// 加载偏移，只有位置无关的可执行文件不为 0
+    pub load_bias: usize,
+    /// 解释器的加载偏移，即辅助向量中的 `AT_BASE`，没有解释器时为 `None`
+    pub interp_base: Option<VirtualAddress>,
+    /// 程序头在内存中的地址，程序头不在任何 `Load` 段中时为 `None`
+    pub phdr: Option<VirtualAddress>,
+    /// 每个程序头的大小
+    pub phent: usize,
+    /// 程序头的个数
+    pub phnum: usize,
+    /// 栈是否可执行，由 `PT_GNU_STACK` 决定，没有这个段时不可执行
+    pub executable_stack: bool,
+}
+
+/// 读取文件开头的 ELF 头和程序头，用于构建 [`ElfFile`]
+pub fn read_headers(inode: &Arc<dyn INode>) -> Result<Vec<u8>, ElfError> {
+    let size = inode.metadata()?.size;
+    let mut headers = vec![0u8; min(size, PAGE_SIZE)];
+    read_exact(inode, 0, &mut headers)?;
+    // 程序头一般紧跟在 ELF 头之后，超出第一页时再读入剩余的部分
+    let ph_end = {
+        let file = ElfFile::new(&headers).map_err(ElfError::Malformed)?;
+        let ph_size = file.header.pt2.ph_entry_size() as usize * file.header.pt2.ph_count() as usize;
+        file.header.pt2.ph_offset() as usize + ph_size
+    };
+    if ph_end > headers.len() {
+        if ph_end > size {
+            return Err(ElfError::Malformed("program headers are out of file"));
+        }
+        headers.resize(ph_end, 0);
+        read_exact(inode, 0, &mut headers)?;
+    }
+    Ok(headers)
+}
+
+/// 从文件的 `offset` 处读满 `buf`，可以缓存的文件经过页缓存读取
+pub fn read_exact(inode: &Arc<dyn INode>, offset: usize, buf: &mut [u8]) -> Result<(), ElfError> {
+    let count = if is_cacheable(inode) {
+        PAGE_CACHE.read(inode, offset, buf)?
+    } else {
+        inode.read_at(offset, buf)?
+    };
+    if count == buf.len() {
+        Ok(())
+    } else {
+        Err(ElfError::Malformed("unexpected end of file"))
+    }
+}
+
+/// 检查文件是否是 64 位小端的 RISC-V ELF 文件
+pub fn check_header(file: &ElfFile) -> Result<(), ElfError> {
+    if file.header.pt1.class() != Class::SixtyFour || file.header.pt1.data() != Data::LittleEndian {
+        return Err(ElfError::UnsupportedArch);
+    }
+    // 直接读取 e_machine，不依赖 xmas_elf 对机器类型的枚举
+    let machine = u16::from_le_bytes([file.input[18], file.input[19]]);
+    if machine != EM_RISCV {
+        return Err(ElfError::UnsupportedArch);
+    }
+    Ok(())
+}
@@ -9,6 +9,7 @@
 use super::*;
 use crate::memory::*;
 use algorithm::*;
+use alloc::vec::Vec;
 use lazy_static::*;
 use spin::Mutex;
 
@@ -19,7 +20,7 @@ use spin::Mutex;
 // Range 单位是*一个页面*，所以需要把 地址 转换为 页号
 lazy_static! {
     /// 帧分配器，是一种共享*资源*，需要加锁
-    /// 默认 FrameAllocator： AllocatorImpl， 即 StackedAllocator
+    /// 默认 FrameAllocator： AllocatorImpl， 即 IntervalAllocator
     pub static ref FRAME_ALLOCATOR: Mutex<FrameAllocator< AllocatorImpl >> = Mutex::new(FrameAllocator::new(Range::from(
             PhysicalPageNumber::ceil(PhysicalAddress::from(*KERNEL_END_ADDRESS))..PhysicalPageNumber::floor(MEMORY_END_ADDRESS),
         )
@@ -32,6 +33,10 @@ pub struct FrameAllocator<T: Allocator> {
     start_ppn: PhysicalPageNumber,
     /// 分配器
     allocator: T,
+    /// 可分配的帧总数
+    capacity: usize,
+    /// 已分配的帧数
+    allocated: usize,
 }
 
 impl<T: Allocator> FrameAllocator<T> {
@@ -40,15 +45,33 @@ impl<T: Allocator> FrameAllocator<T> {
         FrameAllocator {
             start_ppn: range.into().start,  // FrameAllocator 管理的初始页号
             allocator: T::new(range.into().len()), // 初始化分配器，容量是range.into().len()，从start_ppn开始计算
+            capacity: range.into().len(),
+            allocated: 0,
         }
     }
 
     /// 分配帧，如果没有剩余则返回 `Err`
     pub fn alloc(&mut self) -> MemoryResult<FrameTracker> {
-        self.allocator
+        let frame = self.allocator
             .alloc() // self.allocator 返回分配页面的起始物理页号
             .ok_or("no available frame to allocate")
-            .map(|offset| FrameTracker(self.start_ppn + offset)) // 转换为实际的物理页号
+            .map(|offset| FrameTracker(self.start_ppn + offset))?; // 转换为实际的物理页号
+        self.allocated += 1;
+        Ok(frame)
+    }
+
+    /// 分配 `count` 个连续的帧，起始物理页号是 `align` 的倍数，用于映射大页
+    ///
+    /// 每个帧仍由各自的 [`FrameTracker`] 管理，可以分别释放
+    pub fn alloc_contiguous(&mut self, count: usize, align: usize) -> MemoryResult<Vec<FrameTracker>> {
+        let offset = self
+            .allocator
+            .alloc_contiguous(count, align, self.start_ppn.0 % align)
+            .ok_or("no contiguous frames to allocate")?;
+        self.allocated += count;
+        Ok((0..count)
+            .map(|i| FrameTracker(self.start_ppn + offset + i))
+            .collect())
     }
 
     /// 将被释放的帧添加到空闲列表的尾部
@@ -56,5 +79,16 @@ impl<T: Allocator> FrameAllocator<T> {
     /// 这个函数会在 [`FrameTracker`] 被 drop 时自动调用，不应在其他地方调用
     pub(super) fn dealloc(&mut self, frame: &FrameTracker) {
         self.allocator.dealloc(frame.page_number() - self.start_ppn); // 转换为 本 FrameAllocator 管理的区间为基准的 页号
+        self.allocated -= 1;
+    }
+
+    /// 剩余可分配的帧数
+    pub fn free_frames(&self) -> usize {
+        self.capacity - self.allocated
+    }
+
+    /// 可分配的帧总数
+    pub fn total_frames(&self) -> usize {
+        self.capacity
     }
 }
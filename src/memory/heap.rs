@@ -33,6 +33,12 @@ pub fn init() {
     }
 }
 
+/// 堆空间的使用情况，返回（总大小，已分配大小），单位为字节
+pub fn stats() -> (usize, usize) {
+    let heap = HEAP.lock();
+    (heap.stats_total_bytes(), heap.stats_alloc_actual())
+}
+
 /// 空间分配错误的回调，直接 panic 退出
 #[alloc_error_handler]
 fn alloc_error_handler(_: alloc::alloc::Layout) -> ! {
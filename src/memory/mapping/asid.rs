@@ -0,0 +1,95 @@
+//! 地址空间标识符（ASID）的分配 [`AsidAllocator`]
+//!
+//! `satp` 中带有 ASID，TLB 中的项按 ASID 区分，切换页表时不需要刷新整个 TLB。
+//! ASID 按代分配：每个 [`Mapping`](super::Mapping) 记录分配到的代和 ASID，
+//! ASID 用完时进入下一代并刷新整个 TLB，之前各代分配的 ASID 全部作废，在下次激活时重新分配。
+//!
+//! ASID 0 保留不用。硬件不支持 ASID 时总是使用 0，每次切换页表都刷新整个 TLB。
+
+use crate::process::Lock;
+use lazy_static::*;
+
+/// `satp` 中 ASID 的位置
+pub const SATP_ASID_SHIFT: usize = 44;
+
+/// Sv39 中 ASID 最多的位数
+const MAX_ASID_BITS: usize = 16;
+
+lazy_static! {
+    /// 全局的 ASID 分配器
+    ///
+    /// 切换线程时会在中断处理中分配 ASID，所以使用关闭中断的 [`Lock`]
+    pub static ref ASID_ALLOCATOR: Lock<AsidAllocator> = Lock::new(AsidAllocator::new());
+}
+
+/// 按代分配 ASID
+pub struct AsidAllocator {
+    /// 当前的代，从 1 开始，0 表示尚未分配
+    generation: usize,
+    /// 下一个分配的 ASID
+    next: usize,
+    /// 硬件支持的最大 ASID，为 0 时不支持 ASID
+    max: usize,
+}
+
+impl AsidAllocator {
+    /// 创建分配器，并检测硬件支持的 ASID 位数
+    fn new() -> Self {
+        Self {
+            generation: 1,
+            next: 1,
+            max: detect_max_asid(),
+        }
+    }
+
+    /// 取得 `tag` 对应的 ASID，`tag` 不属于当前的代时分配新的 ASID 并更新 `tag`
+    ///
+    /// `tag` 为 [`Mapping`](super::Mapping) 中记录的「代 << 16 | ASID」。
+    /// 返回 ASID 以及是否需要在切换页表之后刷新整个 TLB
+    pub fn asid(&mut self, tag: &mut usize) -> (usize, bool) {
+        if self.max == 0 {
+            return (0, true);
+        }
+        if *tag >> MAX_ASID_BITS == self.generation {
+            return (*tag & ((1 << MAX_ASID_BITS) - 1), false);
+        }
+        let mut rollover = false;
+        if self.next > self.max {
+            // ASID 用完，进入下一代
+            self.generation += 1;
+            self.next = 1;
+            rollover = true;
+        }
+        let asid = self.next;
+        self.next += 1;
+        *tag = (self.generation << MAX_ASID_BITS) | asid;
+        (asid, rollover)
+    }
+
+    /// `tag` 中属于当前代的 ASID，已经作废时返回 `None`
+    ///
+    /// 硬件不支持 ASID 时总是返回 0
+    pub fn current(&self, tag: usize) -> Option<usize> {
+        if self.max == 0 {
+            Some(0)
+        } else if tag >> MAX_ASID_BITS == self.generation {
+            Some(tag & ((1 << MAX_ASID_BITS) - 1))
+        } else {
+            None
+        }
+    }
+}
+
+/// 向 `satp` 的 ASID 字段写入全 1 再读出，得到硬件支持的最大 ASID
+fn detect_max_asid() -> usize {
+    let satp: usize;
+    let probed: usize;
+    unsafe {
+        llvm_asm!("csrr $0, satp" : "=r"(satp) ::: "volatile");
+        llvm_asm!("csrw satp, $0" :: "r"(satp | (((1 << MAX_ASID_BITS) - 1) << SATP_ASID_SHIFT)) :: "volatile");
+        llvm_asm!("csrr $0, satp" : "=r"(probed) ::: "volatile");
+        llvm_asm!("csrw satp, $0" :: "r"(satp) :: "volatile");
+        llvm_asm!("sfence.vma" :::: "volatile");
+    }
+    (probed >> SATP_ASID_SHIFT) & ((1 << MAX_ASID_BITS) - 1)
+}
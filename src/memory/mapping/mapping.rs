@@ -3,6 +3,7 @@
 //! 许多方法返回 [`Result`]，如果出现错误会返回 `Err(message)`。设计目标是，此时如果终止线程，则不会产生后续问题。
 //! 但是如果错误是由操作系统代码逻辑产生的，则会直接 panic。
 
+use super::asid::{ASID_ALLOCATOR, SATP_ASID_SHIFT};
 use crate::memory::{
     address::*,
     config::PAGE_SIZE,
@@ -10,33 +11,61 @@ use crate::memory::{
     mapping::{Flags, MapType, PageTable, PageTableEntry, PageTableTracker, Segment},
     MemoryResult,
 };
-use alloc::{collections::VecDeque, vec, vec::Vec};
+use alloc::{collections::BTreeMap, sync::Arc, vec, vec::Vec};
 use core::cmp::min;
 use core::ptr::slice_from_raw_parts_mut;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// 各级页表中一个页表项对应的页数，依次为 1 GiB、2 MiB 和 4 KiB 的页面
+const LEVEL_PAGES: [usize; 3] = [1 << 18, 1 << 9, 1];
+
+/// 根页表中属于内核（地址空间高半部分）的页表项
+const KERNEL_ROOT_ENTRIES: core::ops::Range<usize> = 256..512;
 
 #[derive(Default)]
 /// 某个线程/进程的内存映射关系
 /// 页表也是需要我们去分配页面来存储的。
 pub struct Mapping {
-    /// 保存所有页表所使用到的页面
-    page_tables: Vec<PageTableTracker>,
+    /// 保存所有页表所使用到的页面，按页号索引，以便释放不再使用的页表
+    page_tables: BTreeMap<PhysicalPageNumber, PageTableTracker>,
     /// 根页表的物理页号
     root_ppn: PhysicalPageNumber,
-    /// 所有分配的物理页面映射信息，存放了进程所用到的页面。
-    mapped_pairs: VecDeque<(VirtualPageNumber, FrameTracker)>,
+    /// 所有分配的物理页面映射信息，按虚拟页号索引，存放了进程所用到的页面。
+    /// 共享的页面可能同时被页缓存或其他进程引用
+    mapped_pairs: BTreeMap<VirtualPageNumber, Arc<FrameTracker>>,
+    /// 分配到的 ASID 及其所属的代，见 [`AsidAllocator`](super::asid::AsidAllocator)
+    asid: AtomicUsize,
 }
 
 impl Mapping {
-    /// 实现页表的激活，也就是把 satp 寄存器更新并刷新 TLB
+    /// 实现页表的激活，也就是把 satp 寄存器更新
     /// 将当前的映射加载到 `satp` 寄存器并记录
+    ///
+    /// TLB 中的项按 ASID 区分，只有 ASID 用完进入下一代时才需要刷新整个 TLB。
+    /// 如果当前页表就是自身（例如同一进程中的线程切换），则什么也不做
     pub fn activate(&self) {
-        // satp 低 27 位为页号，高 4 位为模式，8 表示 Sv39
-        let new_satp = self.root_ppn.0 | (8 << 60);
+        let (asid, flush) = {
+            let mut tag = self.asid.load(Ordering::Relaxed);
+            let result = ASID_ALLOCATOR.lock().asid(&mut tag);
+            self.asid.store(tag, Ordering::Relaxed);
+            result
+        };
+        // satp 低 44 位为页号，44 至 59 位为 ASID，高 4 位为模式，8 表示 Sv39
+        let new_satp = self.root_ppn.0 | (asid << SATP_ASID_SHIFT) | (8 << 60);
+        let current_satp: usize;
+        unsafe {
+            llvm_asm!("csrr $0, satp" : "=r"(current_satp) ::: "volatile");
+        }
+        if current_satp == new_satp && !flush {
+            return;
+        }
         unsafe {
             // 将 new_satp 的值写到 satp 寄存器
             llvm_asm!("csrw satp, $0" :: "r"(new_satp) :: "volatile");
-            // 刷新 TLB
-            llvm_asm!("sfence.vma" :::: "volatile");
+            // 之前各代的 ASID 已经作废，需要刷新 TLB
+            if flush {
+                llvm_asm!("sfence.vma" :::: "volatile");
+            }
         }
     }
 
@@ -44,23 +73,49 @@ impl Mapping {
     pub fn new() -> MemoryResult<Mapping> {
         let root_table = PageTableTracker::new(FRAME_ALLOCATOR.lock().alloc()?);
         let root_ppn = root_table.page_number();
+        let mut page_tables = BTreeMap::new();
+        page_tables.insert(root_ppn, root_table);
         Ok(Mapping {
-            page_tables: vec![root_table],
+            page_tables,
             root_ppn,
-            mapped_pairs: VecDeque::new(),
+            mapped_pairs: BTreeMap::new(),
+            asid: AtomicUsize::new(0),
         })
     }
 
+    /// 与内核的映射共享地址空间的高半部分
+    ///
+    /// 直接复制 `kernel` 根页表中的高半部分页表项，之后的页表由 `kernel` 持有。
+    /// 因此内核的映射建立之后不能再修改高半部分的根页表项。
+    pub fn share_kernel(&mut self, kernel: &Mapping) {
+        let root_table: &mut PageTable = PhysicalAddress::from(self.root_ppn).deref_kernel();
+        let kernel_table: &PageTable = PhysicalAddress::from(kernel.root_ppn).deref_kernel();
+        root_table.entries[KERNEL_ROOT_ENTRIES]
+            .copy_from_slice(&kernel_table.entries[KERNEL_ROOT_ENTRIES]);
+    }
+
     /// 找到给定虚拟页号的三级页表项
     ///
     /// 如果找不到对应的页表项，则会相应创建页表
     /// 实现对页表的查找，并利用该函数实现对虚拟页号到物理页号的映射
     pub fn find_entry(&mut self, vpn: VirtualPageNumber) -> MemoryResult<&mut PageTableEntry> {
+        self.find_entry_at(vpn, 2)
+    }
+
+    /// 找到给定虚拟页号在第 `level` 级页表中的页表项，`level` 为 0、1、2 时分别对应大小为
+    /// 1 GiB、2 MiB、4 KiB 的页面
+    ///
+    /// 如果找不到对应的页表项，则会相应创建页表；途经的大页会被拆分为下一级页表
+    fn find_entry_at(
+        &mut self,
+        vpn: VirtualPageNumber,
+        level: usize,
+    ) -> MemoryResult<&mut PageTableEntry> {
         // 从根页表开始向下查询
         // 这里不用 self.page_tables[0] 避免后面产生 borrow-check 冲突（我太菜了）
         let root_table: &mut PageTable = PhysicalAddress::from(self.root_ppn).deref_kernel();
         let mut entry = &mut root_table.entries[vpn.levels()[0]];
-        for vpn_slice in &vpn.levels()[1..] {
+        for (depth, vpn_slice) in vpn.levels()[1..=level].iter().enumerate() {
             if entry.is_empty() {
                 // 如果页表不存在，则需要分配一个新的页表
                 let new_table = PageTableTracker::new(FRAME_ALLOCATOR.lock().alloc()?);
@@ -68,27 +123,159 @@ impl Mapping {
                 // 将新页表的页号写入当前的页表项
                 *entry = PageTableEntry::new(Some(new_ppn), Flags::VALID);
                 // 保存页表
-                self.page_tables.push(new_table);
+                self.page_tables.insert(new_ppn, new_table);
+            } else if !entry.has_next_level() {
+                // 大页拆分为下一级页表，其中每一项映射大页中相应的部分，权限不变
+                let mut new_table = PageTableTracker::new(FRAME_ALLOCATOR.lock().alloc()?);
+                for (i, sub_entry) in new_table.entries.iter_mut().enumerate() {
+                    *sub_entry = PageTableEntry::new(
+                        Some(entry.page_number() + i * LEVEL_PAGES[depth + 1]),
+                        entry.flags(),
+                    );
+                }
+                *entry = PageTableEntry::new(Some(new_table.page_number()), Flags::VALID);
+                self.page_tables.insert(new_table.page_number(), new_table);
             }
             // 进入下一级页表（使用偏移量来访问物理地址）
             entry = &mut entry.get_next_table().entries[*vpn_slice];
         }
-        // 此时 entry 位于第三级页表
+        // 此时 entry 位于第 level 级页表
         Ok(entry)
     }
 
+    /// 找到给定虚拟页号在第 `level` 级页表中的页表项，不会创建页表
+    ///
+    /// 途经的页表项必须指向下一级页表，否则返回 `None`
+    fn entry_at(&self, vpn: VirtualPageNumber, level: usize) -> Option<&'static mut PageTableEntry> {
+        let root_table: &mut PageTable = PhysicalAddress::from(self.root_ppn).deref_kernel();
+        let mut entry = &mut root_table.entries[vpn.levels()[0]];
+        for vpn_slice in &vpn.levels()[1..=level] {
+            if entry.is_empty() || !entry.has_next_level() {
+                return None;
+            }
+            entry = &mut entry.get_next_table().entries[*vpn_slice];
+        }
+        Some(entry)
+    }
+
+    /// 从第 `level` 级开始向上，释放映射 `vpn` 的路径上已经没有任何页表项的页表（根页表除外）
+    ///
+    /// 返回是否释放了页表
+    fn free_empty_tables(&mut self, vpn: VirtualPageNumber, level: usize) -> bool {
+        let mut freed = false;
+        for table_level in (1..=level).rev() {
+            let parent = match self.entry_at(vpn, table_level - 1) {
+                Some(parent) if !parent.is_empty() && parent.has_next_level() => parent,
+                _ => break,
+            };
+            if parent.get_next_table().entries.iter().any(|entry| !entry.is_empty()) {
+                break;
+            }
+            let ppn = parent.page_number();
+            parent.clear();
+            self.page_tables.remove(&ppn);
+            freed = true;
+        }
+        freed
+    }
+
+    /// 找到映射给定虚拟页号的叶子页表项，以及它所在的级别，不会创建页表
+    fn find_leaf(&self, vpn: VirtualPageNumber) -> Option<(&'static mut PageTableEntry, usize)> {
+        let root_table: &mut PageTable = PhysicalAddress::from(self.root_ppn).deref_kernel();
+        let mut entry = &mut root_table.entries[vpn.levels()[0]];
+        let mut level = 0;
+        while level < 2 && !entry.is_empty() && entry.has_next_level() {
+            level += 1;
+            entry = &mut entry.get_next_table().entries[vpn.levels()[level]];
+        }
+        if entry.is_empty() {
+            None
+        } else {
+            Some((entry, level))
+        }
+    }
+
+    /// 为从 `vpn` 开始、到 `end` 之前的页面分配物理页，返回页面的级别和按顺序排列的物理页
+    ///
+    /// 对齐、足够大且尚未建立下一级页表的部分尝试使用大页，分配不到连续的物理页时退回使用更小的页面
+    fn alloc_frames(
+        &mut self,
+        vpn: VirtualPageNumber,
+        end: VirtualPageNumber,
+    ) -> MemoryResult<(usize, Vec<FrameTracker>)> {
+        for (level, &pages) in LEVEL_PAGES[..2].iter().enumerate() {
+            if !Self::covers_leaf(vpn, level, end) || !self.find_entry_at(vpn, level)?.is_empty() {
+                continue;
+            }
+            let frames = FRAME_ALLOCATOR.lock().alloc_contiguous(pages, pages);
+            if let Ok(frames) = frames {
+                return Ok((level, frames));
+            }
+        }
+        Ok((2, vec![FRAME_ALLOCATOR.lock().alloc()?]))
+    }
+
+    /// 用 `init_data` 中对应 `vpn` 的部分填充一页，其余部分为 0
+    fn fill_page(segment: &Segment, vpn: VirtualPageNumber, init_data: Option<&[u8]>, frame: &mut FrameTracker) {
+        for byte in frame.iter_mut() {
+            *byte = 0;
+        }
+        // 如果提供了数据，则使用这些数据来填充页面
+        if let Some(init_data) = init_data {
+            if !init_data.is_empty() {
+                // 这里必须进行一些调整，因为传入的数据可能并非按照整页对齐
+
+                // 拷贝时必须考虑区间与整页不对齐的情况
+                //    start（仅第一页时非零）
+                //      |        stop（仅最后一页时非零）
+                // 0    |---data---|          4096
+                // |------------page------------|
+                // 数据可能比区间短（例如 ELF 中的 .bss），超出数据的部分保持为 0
+                let page_address = VirtualAddress::from(vpn);
+                let data_end = segment.range.start + init_data.len();
+                if page_address >= data_end {
+                    return;
+                }
+                let start = if segment.range.start > page_address {
+                    segment.range.start - page_address
+                } else {
+                    0
+                };
+                let stop = min(PAGE_SIZE, data_end - page_address);
+                // 计算来源和目标区间并进行拷贝
+                let dst_slice = &mut frame[start..stop];
+                let src_slice = &init_data[(page_address + start - segment.range.start)
+                    ..(page_address + stop - segment.range.start)];
+                dst_slice.copy_from_slice(src_slice);
+            }
+        }
+    }
+
+    /// 检查叶子页表项的权限
+    ///
+    /// RWX 全为 0 的有效页表项会被硬件当作指向下一级页表，不能写入页表
+    pub fn check_leaf_flags(flags: Flags) -> MemoryResult<()> {
+        if flags.intersects(Flags::READABLE | Flags::WRITABLE | Flags::EXECUTABLE) {
+            Ok(())
+        } else {
+            Err("page must be readable, writable or executable")
+        }
+    }
+
     /// 为给定的虚拟 / 物理页号建立映射关系
     fn map_one(
         &mut self,
         vpn: VirtualPageNumber,
         ppn: Option<PhysicalPageNumber>,
         flags: Flags,
+        asid: Option<usize>,
     ) -> MemoryResult<()> {
         // 定位到页表项
         let entry = self.find_entry(vpn)?;
         assert!(entry.is_empty(), "virtual address is already mapped");
         // 页表项为空，则写入内容
         *entry = PageTableEntry::new(ppn, flags);
+        Self::flush_tlb(asid, vpn);
         Ok(())
     }
 
@@ -98,11 +285,22 @@ impl Mapping {
     /// 未被分配物理页面的虚拟页号暂时不会写入页表当中，它们会在发生 PageFault 后再建立页表项。
     /// 为其增加一个参数表示用于初始化的数据
     pub fn map(&mut self, segment: &Segment, init_data: Option<&[u8]>) -> MemoryResult<()> {
+        Self::check_leaf_flags(segment.flags)?;
         match segment.map_type {
             // 线性映射，直接对虚拟地址进行转换
+            // 尽量使用 1 GiB 或 2 MiB 的大页，以减少页表的数量
             MapType::Linear => {
-                for vpn in segment.page_range().iter() {
-                    self.map_one(vpn, Some(vpn.into()), segment.flags | Flags::VALID)?;
+                let range = segment.page_range();
+                let mut vpn = range.start;
+                while vpn < range.end {
+                    // 选择起始地址对齐且不超出区间的最大页面
+                    let level = (0..3)
+                        .find(|&level| Self::covers_leaf(vpn, level, range.end))
+                        .unwrap();
+                    let entry = self.find_entry_at(vpn, level)?;
+                    assert!(entry.is_empty(), "virtual address is already mapped");
+                    *entry = PageTableEntry::new(Some(vpn.into()), segment.flags | Flags::VALID);
+                    vpn += LEVEL_PAGES[level];
                 }
                 // 拷贝数据
                 if let Some(data) = init_data {
@@ -112,60 +310,150 @@ impl Mapping {
                     }
                 }
             }
-            // 需要分配帧进行映射
+            // 需要分配帧进行映射，对齐且足够大的部分尽量使用大页
             MapType::Framed => {
-                for vpn in segment.page_range().iter() {
-                    // 页面的数据，默认为全零
-                    let mut page_data = [0u8; PAGE_SIZE];
-                    // 如果提供了数据，则使用这些数据来填充 page_data
-                    if let Some(init_data) = init_data {
-                        if !init_data.is_empty() {
-                            // 这里必须进行一些调整，因为传入的数据可能并非按照整页对齐
-
-                            // 拷贝时必须考虑区间与整页不对齐的情况
-                            //    start（仅第一页时非零）
-                            //      |        stop（仅最后一页时非零）
-                            // 0    |---data---|          4096
-                            // |------------page------------|
-                            let page_address = VirtualAddress::from(vpn);
-                            let start = if segment.range.start > page_address {
-                                segment.range.start - page_address
-                            } else {
-                                0
-                            };
-                            let stop = min(PAGE_SIZE, segment.range.end - page_address);
-                            // 计算来源和目标区间并进行拷贝
-                            let dst_slice = &mut page_data[start..stop];
-                            let src_slice = &init_data[(page_address + start - segment.range.start)
-                                ..(page_address + stop - segment.range.start)];
-                            dst_slice.copy_from_slice(src_slice);
-                        }
-                    };
-
-                    // 建立映射
-                    let mut frame = FRAME_ALLOCATOR.lock().alloc()?;
-                    // 更新页表
-                    self.map_one(vpn, Some(frame.page_number()), segment.flags)?;
-                    // 写入数据
-                    (*frame).copy_from_slice(&page_data);
-                    // 保存
-                    self.mapped_pairs.push_back((vpn, frame));
+                let range = segment.page_range();
+                let asid = self.current_asid();
+                let mut vpn = range.start;
+                while vpn < range.end {
+                    // 分配物理页并更新页表，大页对应连续的物理页
+                    let (level, frames) = self.alloc_frames(vpn, range.end)?;
+                    let entry = self.find_entry_at(vpn, level)?;
+                    assert!(entry.is_empty(), "virtual address is already mapped");
+                    *entry = PageTableEntry::new(Some(frames[0].page_number()), segment.flags);
+                    Self::flush_tlb(asid, vpn);
+                    // 写入数据并保存，大页中的每一页仍然分别记录
+                    for (i, mut frame) in frames.into_iter().enumerate() {
+                        Self::fill_page(segment, vpn + i, init_data, &mut frame);
+                        self.mapped_pairs.insert(vpn + i, Arc::new(frame));
+                    }
+                    vpn += LEVEL_PAGES[level];
                 }
             }
+            // 共享的页面必须通过 map_shared 提供
+            MapType::Shared => return Err("shared segment must be mapped with its frames"),
+            // 文件页面在缺页时才通过 map_shared 映射
+            MapType::File => {}
+        }
+        Ok(())
+    }
+
+    /// 将一段映射到给定的物理页上，`frames` 按顺序对应 `segment` 中的每一页
+    pub fn map_shared(&mut self, segment: &Segment, frames: Vec<Arc<FrameTracker>>) -> MemoryResult<()> {
+        assert_eq!(segment.page_range().len(), frames.len());
+        Self::check_leaf_flags(segment.flags)?;
+        let asid = self.current_asid();
+        for (vpn, frame) in segment.page_range().iter().zip(frames.into_iter()) {
+            self.map_one(vpn, Some(frame.page_number()), segment.flags, asid)?;
+            self.mapped_pairs.insert(vpn, frame);
         }
         Ok(())
     }
 
+    /// 虚拟页映射到的物理页，可以用来与其他映射共享
+    pub fn frame(&self, vpn: VirtualPageNumber) -> Option<Arc<FrameTracker>> {
+        self.mapped_pairs.get(&vpn).cloned()
+    }
+
+    /// 修改一段已经映射的页面的权限，并刷新 TLB
+    ///
+    /// 只修改大页的一部分时，会先将其拆分
+    pub fn protect(&mut self, segment: &Segment) -> MemoryResult<()> {
+        Self::check_leaf_flags(segment.flags)?;
+        let range = segment.page_range();
+        let asid = self.current_asid();
+        let mut vpn = range.start;
+        while vpn < range.end {
+            let (entry, level) = match self.find_leaf(vpn) {
+                Some(leaf) => leaf,
+                // 按需加载的文件页面可能还没有映射
+                None if segment.map_type == MapType::File => {
+                    vpn += 1;
+                    continue;
+                }
+                None => panic!("page is not mapped"),
+            };
+            if !Self::covers_leaf(vpn, level, range.end) {
+                self.find_entry_at(vpn, level + 1)?;
+                continue;
+            }
+            entry.set_flags(segment.flags | Flags::VALID);
+            Self::flush_tlb(asid, vpn);
+            vpn += LEVEL_PAGES[level];
+        }
+        Ok(())
+    }
+
+    /// 查找虚拟页号在此映射中对应的物理页号，不会创建页表
+    pub fn translate(&self, vpn: VirtualPageNumber) -> Option<PhysicalPageNumber> {
+        // 大页中还要加上页面在其中的偏移
+        self.find_leaf(vpn)
+            .map(|(entry, level)| entry.page_number() + vpn.0 % LEVEL_PAGES[level])
+    }
+
+    /// 从 `vpn` 开始、到 `end` 之前的区间是否完整覆盖了 `vpn` 所在的第 `level` 级页面
+    fn covers_leaf(vpn: VirtualPageNumber, level: usize, end: VirtualPageNumber) -> bool {
+        vpn.0 % LEVEL_PAGES[level] == 0 && vpn.0 + LEVEL_PAGES[level] <= end.0
+    }
+
+    /// 此映射当前的 ASID，已经作废时返回 `None`
+    ///
+    /// 修改一段页面之前读取一次，避免每一页都要获取 [`ASID_ALLOCATOR`] 的锁
+    fn current_asid(&self) -> Option<usize> {
+        ASID_ALLOCATOR.lock().current(self.asid.load(Ordering::Relaxed))
+    }
+
+    /// 刷新 `asid` 下的所有 TLB 项，包括指向下一级页表的项
+    fn flush_tlb_all(asid: Option<usize>) {
+        if let Some(asid) = asid {
+            unsafe { llvm_asm!("sfence.vma zero, $0" :: "r"(asid) :: "volatile") };
+        }
+    }
+
+    /// 刷新某一页在 `asid` 下的 TLB
+    ///
+    /// ASID 已经作废时，TLB 中不会有相应的项，不需要刷新
+    fn flush_tlb(asid: Option<usize>, vpn: VirtualPageNumber) {
+        if let Some(asid) = asid {
+            let address = VirtualAddress::from(vpn).0;
+            unsafe { llvm_asm!("sfence.vma $0, $1" :: "r"(address), "r"(asid) :: "volatile") };
+        }
+    }
+
     /// 移除一段映射
+    ///
+    /// 只移除大页的一部分时，会先将其拆分。移除后变空的页表会被释放
     pub fn unmap(&mut self, segment: &Segment) {
-        for vpn in segment.page_range().iter() {
-            let entry = self.find_entry(vpn).unwrap();
-            assert!(!entry.is_empty());
+        let range = segment.page_range();
+        let asid = self.current_asid();
+        let mut vpn = range.start;
+        let mut tables_freed = false;
+        while vpn < range.end {
+            let (entry, level) = match self.find_leaf(vpn) {
+                Some(leaf) => leaf,
+                None if segment.map_type == MapType::File => {
+                    vpn += 1;
+                    continue;
+                }
+                None => panic!("page is not mapped"),
+            };
+            if !Self::covers_leaf(vpn, level, range.end) {
+                self.find_entry_at(vpn, level + 1).unwrap();
+                continue;
+            }
             // 从页表中清除项
             entry.clear();
+            Self::flush_tlb(asid, vpn);
+            tables_freed |= self.free_empty_tables(vpn, level);
+            vpn += LEVEL_PAGES[level];
+        }
+        // 指向下一级页表的项只能通过不带地址的 sfence.vma 刷新
+        if tables_freed {
+            Self::flush_tlb_all(asid);
         }
-        // 移除相应的页面
-        self.mapped_pairs.retain(|(vpn, _)| !segment.page_range().contains(*vpn))
+        // 移除相应的页面：取出区间之内的部分，其余部分放回
+        let mut removed = self.mapped_pairs.split_off(&range.start);
+        self.mapped_pairs.append(&mut removed.split_off(&range.end));
     }
 
     /// 查找虚拟地址对应的物理地址
@@ -180,7 +468,8 @@ impl Mapping {
         let mut current_ppn;
         unsafe {
             llvm_asm!("csrr $0, satp" : "=r"(current_ppn) ::: "volatile");
-            current_ppn ^= 8 << 60;
+            // 去掉模式和 ASID，只保留页号
+            current_ppn &= (1 << SATP_ASID_SHIFT) - 1;
         }
 
         let root_table: &PageTable =
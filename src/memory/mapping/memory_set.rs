@@ -8,27 +8,69 @@
 use crate::memory::{
     address::*,
     config::*,
+    frame::FrameTracker,
     mapping::{Flags, MapType, Mapping, Segment},
     range::Range,
+    elf::{self, ElfError, ElfInfo},
     MemoryResult,
 };
-use alloc::{vec, vec::Vec};
+use crate::fs::{is_cacheable, lookup, DenyWrite, INode, PAGE_CACHE, PATH_MAX};
+use alloc::{collections::BTreeMap, string::String, sync::Arc, vec, vec::Vec};
+use core::cmp::{max, min};
+use lazy_static::*;
 use xmas_elf::{
-    program::{SegmentData, Type},
+    header,
+    program::{ProgramHeader, Type},
     ElfFile,
 };
 
+lazy_static! {
+    /// 内核的映射，所有地址空间的高半部分都共享其中的页表
+    static ref KERNEL_MAPPING: Mapping = MemorySet::kernel_mapping().unwrap();
+}
+
 /// 一个进程所有关于内存空间管理的信息
 pub struct MemorySet {
     /// 维护页表和映射关系
     pub mapping: Mapping,
-    /// 每个字段
-    pub segments: Vec<Segment>,
+    /// 每个字段，按起始页号排序，不包括共享的内核映射
+    pub segments: BTreeMap<VirtualPageNumber, Segment>,
+    /// 堆的起始地址，即 ELF 中最高的 `Load` 段之后的第一页，内核的 `MemorySet` 中为 0
+    pub heap_start: VirtualAddress,
+    /// `File` 类型的 Segment 中的页面所对应的文件
+    pub file_backings: Vec<FileBacking>,
+}
+
+/// 按需从文件加载的一段虚拟页
+#[derive(Clone)]
+pub struct FileBacking {
+    /// 对应的虚拟页
+    pub range: Range<VirtualPageNumber>,
+    /// 提供内容的文件
+    pub inode: Arc<dyn INode>,
+    /// 区间起始处对应的文件页号
+    pub first_page: usize,
+    /// 页面直接映射页缓存，映射期间文件不能被写入
+    pub deny_write: DenyWrite,
 }
 
 impl MemorySet {
-    /// 创建内核重映射
+    /// 创建只包含内核映射的 MemorySet
+    ///
+    /// 新的根页表直接复制 [`static@KERNEL_MAPPING`] 的高半部分，不需要重新建立内核的映射
     pub fn new_kernel() -> MemoryResult<MemorySet> {
+        let mut mapping = Mapping::new()?;
+        mapping.share_kernel(&KERNEL_MAPPING);
+        Ok(MemorySet {
+            mapping,
+            segments: BTreeMap::new(),
+            heap_start: VirtualAddress(0),
+            file_backings: Vec::new(),
+        })
+    }
+
+    /// 建立内核重映射，只在初始化 [`static@KERNEL_MAPPING`] 时调用一次
+    fn kernel_mapping() -> MemoryResult<Mapping> {
         // 在 linker.ld 里面标记的各个字段的起始点，均为 4K 对齐
         extern "C" {
             fn text_start();
@@ -78,19 +120,21 @@ impl MemorySet {
         ];
         let mut mapping = Mapping::new()?;
 
-        // 每个字段在页表中进行映射
+        // 每个字段在页表中进行映射，内核映射在所有地址空间中都相同，标记为全局
         for segment in segments.iter() {
-            mapping.map(segment, None)?;
+            let segment = Segment {
+                flags: segment.flags | Flags::GLOBAL,
+                ..*segment
+            };
+            mapping.map(&segment, None)?;
         }
-        Ok(MemorySet {
-            mapping,
-            segments,
-        })
+        Ok(mapping)
     }
 
     /// 替换 `satp` 以激活页表
     ///
-    /// 如果当前页表就是自身，则不会替换，但仍然会刷新 TLB。
+    /// TLB 中的项按 ASID 区分，切换时不需要刷新，只有 ASID 用完进入下一代或者硬件不支持 ASID 时才刷新整个 TLB。
+    /// 如果当前页表就是自身，则什么也不做
     pub fn activate(&self) {
         self.mapping.activate();
     }
@@ -101,70 +145,547 @@ impl MemorySet {
         assert!(!self.overlap_with(segment.page_range()));
         // 映射
         self.mapping.map(&segment, init_data)?;
-        self.segments.push(segment);
+        self.segments.insert(segment.page_range().start, segment);
+        Ok(())
+    }
+
+    /// 添加一个映射到给定物理页的 [`Segment`]，`frames` 按顺序对应其中每一页
+    pub fn add_shared_segment(
+        &mut self,
+        segment: Segment,
+        frames: Vec<Arc<FrameTracker>>,
+    ) -> MemoryResult<()> {
+        assert!(!self.overlap_with(segment.page_range()));
+        self.mapping.map_shared(&segment, frames)?;
+        self.segments.insert(segment.page_range().start, segment);
         Ok(())
     }
 
+    /// 添加一个 `File` 类型的 [`Segment`]，其中的页面在第一次访问时从页缓存映射
+    ///
+    /// `first_page` 为 Segment 的第一页对应的文件页号
+    pub fn add_file_segment(&mut self, segment: Segment, inode: Arc<dyn INode>, first_page: usize) {
+        assert_eq!(segment.map_type, MapType::File);
+        assert!(!self.overlap_with(segment.page_range()));
+        self.segments.insert(segment.page_range().start, segment);
+        self.file_backings.push(FileBacking {
+            range: segment.page_range(),
+            deny_write: DenyWrite::new(&inode),
+            inode,
+            first_page,
+        });
+    }
+
+    /// 处理缺页异常，为按需加载的文件页面建立映射
+    ///
+    /// 地址不属于 `File` 类型的 Segment、页面已经映射（即权限不符）或读取文件失败时返回 `false`
+    pub fn handle_page_fault(&mut self, address: VirtualAddress) -> bool {
+        let vpn = VirtualPageNumber::floor(address);
+        let flags = match self.segment_containing(vpn) {
+            Some(segment) if segment.map_type == MapType::File => segment.flags,
+            _ => return false,
+        };
+        if self.mapping.translate(vpn).is_some() {
+            return false;
+        }
+        let backing = match self.file_backings.iter().find(|backing| backing.range.contains(vpn)) {
+            Some(backing) => backing,
+            None => return false,
+        };
+        let index = backing.first_page + (vpn - backing.range.start);
+        let frame = match PAGE_CACHE.get_page(&backing.inode, index) {
+            Ok(frame) => frame,
+            Err(_) => return false,
+        };
+        let page = Segment {
+            map_type: MapType::File,
+            range: Range::from(vpn..vpn + 1).into(),
+            flags,
+        };
+        self.mapping.map_shared(&page, vec![frame]).is_ok()
+    }
+
+    /// 为一段虚拟页中尚未加载的文件页面建立映射，用于内核访问用户内存之前
+    pub fn populate(&mut self, range: Range<VirtualPageNumber>) {
+        for vpn in range.iter() {
+            self.handle_page_fault(vpn.into());
+        }
+    }
+
+    /// 移除一段虚拟页的映射
+    ///
+    /// 与之部分重叠的 [`Segment`] 会被拆分，只保留区间之外的部分
+    pub fn remove_range(&mut self, range: Range<VirtualPageNumber>) -> MemoryResult<()> {
+        for piece in self.split_segments(range) {
+            self.mapping.unmap(&piece);
+        }
+        // 区间之外的文件页面仍然按需加载
+        let mut remaining = Vec::new();
+        for backing in self.file_backings.drain(..) {
+            if !backing.range.overlap_with(&range) {
+                remaining.push(backing);
+                continue;
+            }
+            if backing.range.start < range.start {
+                remaining.push(FileBacking {
+                    range: Range::from(backing.range.start..range.start),
+                    ..backing.clone()
+                });
+            }
+            if range.end < backing.range.end {
+                remaining.push(FileBacking {
+                    range: Range::from(range.end..backing.range.end),
+                    inode: backing.inode.clone(),
+                    first_page: backing.first_page + (range.end - backing.range.start),
+                    deny_write: backing.deny_write.clone(),
+                });
+            }
+        }
+        self.file_backings = remaining;
+        Ok(())
+    }
+
+    /// 修改一段虚拟页的权限
+    ///
+    /// 与之部分重叠的 [`Segment`] 会被拆分，区间之内的部分使用新的权限
+    pub fn protect(&mut self, range: Range<VirtualPageNumber>, flags: Flags) -> MemoryResult<()> {
+        // 在拆分 Segment 之前检查，失败时不改变已有的映射
+        Mapping::check_leaf_flags(flags)?;
+        // 文件页面与页缓存共享，不能变为可写
+        let has_file_pages = self.segments.values().any(|segment| {
+            segment.map_type == MapType::File && segment.page_range().overlap_with(&range)
+        });
+        if flags.contains(Flags::WRITABLE) && has_file_pages {
+            return Err("file-backed pages cannot be made writable");
+        }
+        for mut piece in self.split_segments(range) {
+            piece.flags = flags;
+            self.mapping.protect(&piece)?;
+            self.segments.insert(piece.page_range().start, piece);
+        }
+        Ok(())
+    }
+
+    /// 调整以 `start` 开始的 `Framed` 段的结束地址，段不存在时会创建
+    ///
+    /// 新增的页面为全零，移除的页面被释放。`end` 等于 `start` 时移除整个段
+    pub fn resize_framed_segment(
+        &mut self,
+        start: VirtualAddress,
+        end: VirtualAddress,
+        flags: Flags,
+    ) -> MemoryResult<()> {
+        assert!(start <= end);
+        let key = VirtualPageNumber::floor(start);
+        let old_end = match self.segments.get(&key) {
+            Some(segment) if segment.range.start == start && segment.map_type == MapType::Framed => {
+                segment.range.end
+            }
+            Some(_) => return Err("another segment starts at the same page"),
+            None => start,
+        };
+        let old_page_end = VirtualPageNumber::ceil(old_end);
+        let new_page_end = VirtualPageNumber::ceil(end);
+        if new_page_end > old_page_end {
+            let growth = Range::from(old_page_end..new_page_end);
+            if self.overlap_with(growth) {
+                return Err("segment cannot grow into another segment");
+            }
+            self.mapping.map(
+                &Segment {
+                    map_type: MapType::Framed,
+                    range: growth.into(),
+                    flags,
+                },
+                None,
+            )?;
+        } else if new_page_end < old_page_end {
+            self.mapping.unmap(&Segment {
+                map_type: MapType::Framed,
+                range: Range::from(new_page_end..old_page_end).into(),
+                flags,
+            });
+        }
+        if start == end {
+            self.segments.remove(&key);
+        } else {
+            self.segments.insert(
+                key,
+                Segment {
+                    map_type: MapType::Framed,
+                    range: Range::from(start..end),
+                    flags,
+                },
+            );
+        }
+        Ok(())
+    }
+
+    /// 一段虚拟页是否全部属于已有的 [`Segment`]
+    pub fn covers(&self, range: Range<VirtualPageNumber>) -> bool {
+        // 从区间起点开始，依次跳到包含当前页的 Segment 的结尾
+        let mut vpn = range.start;
+        while vpn < range.end {
+            match self.segment_containing(vpn) {
+                Some(segment) => vpn = segment.page_range().end,
+                None => return false,
+            }
+        }
+        true
+    }
+
+    /// 一段用户地址是否全部属于具有 `USER` 以及 `flags` 权限的 [`Segment`]，空区间总是合法
+    pub fn check_user(&self, range: Range<VirtualAddress>, flags: Flags) -> bool {
+        if range.start >= range.end {
+            return true;
+        }
+        if range.end > USER_END_ADDRESS {
+            return false;
+        }
+        let end = VirtualPageNumber::ceil(range.end);
+        let mut vpn = VirtualPageNumber::floor(range.start);
+        while vpn < end {
+            match self.segment_containing(vpn) {
+                Some(segment) if segment.flags.contains(flags | Flags::USER) => {
+                    vpn = segment.page_range().end
+                }
+                _ => return false,
+            }
+        }
+        true
+    }
+
+    /// 找到包含某一页的 [`Segment`]
+    pub fn segment_containing(&self, vpn: VirtualPageNumber) -> Option<&Segment> {
+        self.segments
+            .range(..=vpn)
+            .next_back()
+            .map(|(_, segment)| segment)
+            .filter(|segment| segment.page_range().contains(vpn))
+    }
+
+    /// 在用户地址空间中找到一段长度为 `size` 的未映射区间
+    ///
+    /// 从 `hint` 开始向高地址查找相邻 Segment 之间的空隙，找不到时返回 `None`
+    pub fn find_free_range(&self, size: usize, hint: VirtualAddress) -> Option<Range<VirtualAddress>> {
+        let pages = (size + PAGE_SIZE - 1) / PAGE_SIZE;
+        let mut start = VirtualPageNumber::ceil(hint);
+        // 跳过包含起点的 Segment
+        if let Some(segment) = self.segment_containing(start) {
+            start = segment.page_range().end;
+        }
+        // 之后的 Segment 按起始页号排列，找到第一个足够大的空隙
+        for segment in self.segments.range(start..).map(|(_, segment)| segment) {
+            if segment.page_range().start >= start + pages {
+                break;
+            }
+            start = segment.page_range().end;
+        }
+        let range = Range::from(VirtualAddress::from(start)..VirtualAddress::from(start + pages));
+        if range.end <= USER_END_ADDRESS {
+            Some(range)
+        } else {
+            None
+        }
+    }
+
+    /// 将与 `range` 重叠的 [`Segment`] 拆分，返回并移除落在区间内的部分
+    ///
+    /// 区间之外的部分仍然保留在 `segments` 中，页表不做修改
+    fn split_segments(&mut self, range: Range<VirtualPageNumber>) -> Vec<Segment> {
+        // 与区间重叠的 Segment：起点在区间之前且跨入区间的至多一个，以及起点在区间之内的
+        let mut keys: Vec<VirtualPageNumber> = self
+            .segments
+            .range(..range.start)
+            .next_back()
+            .filter(|(_, segment)| segment.page_range().end > range.start)
+            .map(|(key, _)| *key)
+            .into_iter()
+            .collect();
+        keys.extend(self.segments.range(range.start..range.end).map(|(key, _)| *key));
+
+        let mut inside = Vec::new();
+        let mut outside = Vec::new();
+        for key in keys {
+            let segment = self.segments.remove(&key).unwrap();
+            let page_range = segment.page_range();
+            let overlap_start = max(page_range.start, range.start);
+            let overlap_end = min(page_range.end, range.end);
+            if page_range.start < overlap_start {
+                outside.push(Segment {
+                    range: Range::from(segment.range.start..VirtualAddress::from(overlap_start)),
+                    ..segment
+                });
+            }
+            if overlap_end < page_range.end {
+                outside.push(Segment {
+                    range: Range::from(VirtualAddress::from(overlap_end)..segment.range.end),
+                    ..segment
+                });
+            }
+            inside.push(Segment {
+                range: Range::from(
+                    max(segment.range.start, VirtualAddress::from(overlap_start))
+                        ..min(segment.range.end, VirtualAddress::from(overlap_end)),
+                ),
+                ..segment
+            });
+        }
+        for segment in outside {
+            self.segments.insert(segment.page_range().start, segment);
+        }
+        inside
+    }
+
     /// 移除一个 [`Segment`] 的内存映射
     ///
     /// `segment` 必须已经映射
     pub fn remove_segment(&mut self, segment: &Segment) -> MemoryResult<()> {
         // 找到对应的 segment
-        let segment_index = self
-            .segments
-            .iter()
-            .position(|s| s == segment)
-            .expect("segment to remove cannot be found");
-        self.segments.remove(segment_index);
+        let removed = self.segments.remove(&segment.page_range().start);
+        assert_eq!(removed.as_ref(), Some(segment), "segment to remove cannot be found");
         // 移除映射
         self.mapping.unmap(segment);
         Ok(())
     }
 
-    /// 检测一段内存区域和已有的是否存在重叠区域
-    pub fn overlap_with(&self, range: Range<VirtualPageNumber>) -> bool {
-        for seg in self.segments.iter() {
-            if range.overlap_with(&seg.page_range()) {
-                return true;
+    /// 复制出一个新的地址空间，用于创建子进程
+    ///
+    /// `Framed` 的页面复制内容，`Shared` 的页面与原地址空间共享，`File` 的页面在子进程中重新按需加载
+    pub fn fork(&self) -> MemoryResult<MemorySet> {
+        let mut memory_set = MemorySet::new_kernel()?;
+        for segment in self.segments.values() {
+            match segment.map_type {
+                MapType::Linear => memory_set.add_segment(*segment, None)?,
+                MapType::Framed => {
+                    memory_set.add_segment(*segment, None)?;
+                    for vpn in segment.page_range().iter() {
+                        let source = self.mapping.translate(vpn).unwrap();
+                        let target = memory_set.mapping.translate(vpn).unwrap();
+                        target.deref_kernel().copy_from_slice(source.deref_kernel());
+                    }
+                }
+                MapType::Shared => {
+                    let frames = segment
+                        .page_range()
+                        .iter()
+                        .map(|vpn| self.mapping.frame(vpn).unwrap())
+                        .collect();
+                    memory_set.add_shared_segment(*segment, frames)?;
+                }
+                MapType::File => {
+                    memory_set.segments.insert(segment.page_range().start, *segment);
+                }
             }
         }
-        false
+        memory_set.heap_start = self.heap_start;
+        memory_set.file_backings = self.file_backings.clone();
+        Ok(memory_set)
+    }
+
+    /// 通过内核的线性映射写入此地址空间中已经映射的内存
+    ///
+    /// 不要求此地址空间正在使用，用于在线程运行之前准备用户栈等
+    pub fn write_bytes(&self, address: VirtualAddress, data: &[u8]) -> MemoryResult<()> {
+        let mut position = 0;
+        while position < data.len() {
+            let current = address + position;
+            let page_offset = current.page_offset();
+            let length = min(PAGE_SIZE - page_offset, data.len() - position);
+            let ppn = self
+                .mapping
+                .translate(VirtualPageNumber::floor(current))
+                .ok_or("address is not mapped")?;
+            ppn.deref_kernel()[page_offset..page_offset + length]
+                .copy_from_slice(&data[position..position + length]);
+            position += length;
+        }
+        Ok(())
+    }
+
+    /// 检测一段内存区域和已有的是否存在重叠区域
+    ///
+    /// Segment 互不重叠且按起始页号排列，只需检查起点在区间结尾之前的最后一个
+    pub fn overlap_with(&self, range: Range<VirtualPageNumber>) -> bool {
+        self.segments
+            .range(..range.end)
+            .next_back()
+            .map_or(false, |(_, segment)| segment.page_range().end > range.start)
     }
 
     /// 通过 elf 文件创建内存映射（不包括栈）
-    pub fn from_elf(file: &ElfFile, is_user: bool) -> MemoryResult<MemorySet> {
+    ///
+    /// `file` 只需包含 `inode` 开头的 ELF 头和程序头（见 [`elf::read_headers`]），段的内容从 `inode` 中读取。
+    /// 位置无关的可执行文件（`ET_DYN`）加载到 [`ELF_DYN_BASE`]。
+    /// 每个 `Load` 段映射 `p_memsz` 字节，其中 `p_filesz` 之后的部分填充 0。
+    /// 有 `PT_INTERP` 时，从文件系统中读取解释器并加载到 [`INTERP_BASE`]，线程从解释器的入口开始执行
+    pub fn from_elf(
+        inode: &Arc<dyn INode>,
+        file: &ElfFile,
+        is_user: bool,
+    ) -> Result<(MemorySet, ElfInfo), ElfError> {
         // 建立带有内核映射的 MemorySet
         let mut memory_set = MemorySet::new_kernel()?;
+        let (mut info, interpreter, image_end) =
+            memory_set.load_image(inode, file, is_user, ELF_DYN_BASE)?;
+        // 堆从主程序最高的段之后开始
+        memory_set.heap_start = image_end;
+
+        if let Some(path) = interpreter {
+            let interp_inode = lookup(&path)?;
+            let headers = elf::read_headers(&interp_inode)?;
+            let interp = ElfFile::new(headers.as_slice()).map_err(ElfError::Malformed)?;
+            // 解释器必须是位置无关的，且自身不能再有解释器
+            if !matches!(interp.header.pt2.type_().as_type(), header::Type::SharedObject) {
+                return Err(ElfError::InvalidInterpreter);
+            }
+            let (interp_info, nested, _) =
+                memory_set.load_image(&interp_inode, &interp, is_user, INTERP_BASE)?;
+            if nested.is_some() {
+                return Err(ElfError::InvalidInterpreter);
+            }
+            info.interp_base = Some(VirtualAddress(interp_info.load_bias));
+            info.start = interp_info.entry;
+        }
+        Ok((memory_set, info))
+    }
+
+    /// 将一个 ELF 文件的所有 `Load` 段映射到此地址空间，`ET_DYN` 文件加载到 `dyn_base`
+    ///
+    /// 只读的段在可能时作为 `File` 类型的 Segment 按需加载，同一文件的页面在进程之间共享；
+    /// 其他的段在加载时读入。返回加载信息、`PT_INTERP` 中解释器的路径，以及最高的段之后的第一页
+    fn load_image(
+        &mut self,
+        inode: &Arc<dyn INode>,
+        file: &ElfFile,
+        is_user: bool,
+        dyn_base: VirtualAddress,
+    ) -> Result<(ElfInfo, Option<String>, VirtualAddress), ElfError> {
+        elf::check_header(file)?;
+        let is_load = |program_header: &ProgramHeader| program_header.get_type() == Ok(Type::Load);
+        let load_bias = match file.header.pt2.type_().as_type() {
+            header::Type::Executable => 0,
+            // 将最低的段所在的页放在 dyn_base
+            header::Type::SharedObject => {
+                let lowest = file
+                    .program_iter()
+                    .filter(is_load)
+                    .map(|program_header| program_header.virtual_addr() as usize)
+                    .min()
+                    .ok_or(ElfError::InvalidSegment)?;
+                dyn_base
+                    .0
+                    .checked_sub(lowest / PAGE_SIZE * PAGE_SIZE)
+                    .ok_or(ElfError::InvalidSegment)?
+            }
+            _ => return Err(ElfError::UnsupportedType),
+        };
+        let entry = VirtualAddress((file.header.pt2.entry_point() as usize).wrapping_add(load_bias));
+        let mut info = ElfInfo {
+            entry,
+            start: entry,
+            load_bias,
+            interp_base: None,
+            phdr: None,
+            phent: file.header.pt2.ph_entry_size() as usize,
+            phnum: file.header.pt2.ph_count() as usize,
+            executable_stack: false,
+        };
+        let ph_offset = file.header.pt2.ph_offset() as usize;
+        let file_length = inode.metadata()?.size;
+        let mut entry_is_valid = false;
+        let mut interpreter = None;
+        let mut image_end = VirtualAddress(0);
 
         // 遍历 elf 文件的所有部分
         for program_header in file.program_iter() {
-            if program_header.get_type() != Ok(Type::Load) {
+            match program_header.get_type().map_err(ElfError::Malformed)? {
+                Type::Load => {}
+                Type::Phdr => {
+                    info.phdr = Some(VirtualAddress(program_header.virtual_addr() as usize + load_bias));
+                    continue;
+                }
+                Type::Interp => {
+                    let offset = program_header.offset() as usize;
+                    let size = program_header.file_size() as usize;
+                    if size > PATH_MAX {
+                        return Err(ElfError::Malformed("invalid interpreter path"));
+                    }
+                    let mut path = vec![0u8; size];
+                    elf::read_exact(inode, offset, &mut path)?;
+                    // 路径以 \0 结尾
+                    let path = path.split(|&byte| byte == 0).next().unwrap_or(&[]);
+                    let path = core::str::from_utf8(path)
+                        .map_err(|_| ElfError::Malformed("invalid interpreter path"))?;
+                    interpreter = Some(String::from(path));
+                    continue;
+                }
+                Type::OsSpecific(elf::PT_GNU_STACK) => {
+                    info.executable_stack = program_header.flags().is_execute();
+                    continue;
+                }
+                _ => continue,
+            }
+            // 检查段在文件和地址空间中的位置
+            let offset = program_header.offset() as usize;
+            let file_size = program_header.file_size() as usize;
+            let mem_size = program_header.mem_size() as usize;
+            let file_end = offset.checked_add(file_size).ok_or(ElfError::InvalidSegment)?;
+            let start = (program_header.virtual_addr() as usize)
+                .checked_add(load_bias)
+                .ok_or(ElfError::InvalidSegment)?;
+            let end = start.checked_add(mem_size).ok_or(ElfError::InvalidSegment)?;
+            if file_size > mem_size
+                || file_end > file_length
+                || (is_user && end > USER_END_ADDRESS.0)
+            {
+                return Err(ElfError::InvalidSegment);
+            }
+            if mem_size == 0 {
                 continue;
             }
-            // 从每个字段读取「起始地址」「大小」和「数据」
-            let start = VirtualAddress(program_header.virtual_addr() as usize);
-            let size = program_header.mem_size() as usize;
-            let data: &[u8] =
-                if let SegmentData::Undefined(data) = program_header.get_data(file).unwrap() {
-                    data
-                } else {
-                    return Err("unsupported elf format");
-                };
 
             // 将每一部分作为 Segment 进行映射
+            let flags = Flags::user(is_user)
+                | Flags::readable(program_header.flags().is_read())
+                | Flags::writable(program_header.flags().is_write())
+                | Flags::executable(program_header.flags().is_execute());
+            // 只读、没有 .bss 且在文件中与页对齐的段可以直接映射页缓存
+            let file_backed = !flags.contains(Flags::WRITABLE)
+                && file_size == mem_size
+                && start % PAGE_SIZE == offset % PAGE_SIZE
+                && is_cacheable(inode);
             let segment = Segment {
-                map_type: MapType::Framed,
-                range: Range::from(start..(start + size)),
-                flags: Flags::user(is_user)
-                    | Flags::readable(program_header.flags().is_read())
-                    | Flags::writable(program_header.flags().is_write())
-                    | Flags::executable(program_header.flags().is_execute()),
+                map_type: if file_backed { MapType::File } else { MapType::Framed },
+                range: Range::from(VirtualAddress(start)..VirtualAddress(end)),
+                flags,
             };
+            if self.overlap_with(segment.page_range()) {
+                return Err(ElfError::InvalidSegment);
+            }
 
-            // 建立映射并复制数据
-            memory_set.add_segment(segment, Some(data))?;
+            if file_backed {
+                self.add_file_segment(segment, inode.clone(), offset / PAGE_SIZE);
+            } else {
+                // 读入数据并建立映射，超出 p_filesz 的部分为 0
+                let mut data = vec![0u8; file_size];
+                elf::read_exact(inode, offset, &mut data)?;
+                self.add_segment(segment, Some(&data))?;
+            }
+            if segment.flags.contains(Flags::EXECUTABLE) && (start..end).contains(&info.entry.0) {
+                entry_is_valid = true;
+            }
+            // 没有 PT_PHDR 时，从包含程序头的段中找到程序头的地址
+            let ph_end = ph_offset + info.phent * info.phnum;
+            if info.phdr.is_none() && offset <= ph_offset && ph_end <= file_end {
+                info.phdr = Some(VirtualAddress(start + ph_offset - offset));
+            }
+            image_end = max(image_end, VirtualAddress::from(segment.page_range().end));
         }
 
-        Ok(memory_set)
+        if !entry_is_valid {
+            return Err(ElfError::InvalidEntry);
+        }
+        Ok((info, interpreter, image_end))
     }
 }
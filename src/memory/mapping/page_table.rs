@@ -15,6 +15,11 @@
 
 use super::page_table_entry::PageTableEntry;
 use crate::memory::{address::*, config::PAGE_SIZE, frame::FrameTracker};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// 当前作为页表使用的帧数，用于检查页表是否泄漏
+pub static PAGE_TABLE_FRAMES: AtomicUsize = AtomicUsize::new(0);
+
 /// 存有 512 个页表项的页表
 ///
 /// 注意我们不会使用常规的 Rust 语法来创建 `PageTable`。相反，我们会分配一个物理页，
@@ -45,6 +50,7 @@ impl PageTableTracker {
     pub fn new(frame: FrameTracker) -> Self {
         let mut page_table = Self(frame);
         page_table.zero_init();
+        PAGE_TABLE_FRAMES.fetch_add(1, Ordering::Relaxed);
         page_table
     }
     /// 获取物理页号
@@ -53,6 +59,13 @@ impl PageTableTracker {
     }
 }
 
+/// 页表被释放时更新 [`PAGE_TABLE_FRAMES`]，之后 `FrameTracker` 会释放帧
+impl Drop for PageTableTracker {
+    fn drop(&mut self) {
+        PAGE_TABLE_FRAMES.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
 // PageTableEntry 和 PageTableTracker 都可以 deref 到对应的 PageTable
 // （使用线性映射来访问相应的物理地址）
 impl core::ops::Deref for PageTableTracker {
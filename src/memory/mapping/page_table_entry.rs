@@ -49,6 +49,10 @@ impl PageTableEntry {
                 .set_bits(PAGE_NUMBER_RANGE, 0);
         }
     }
+    /// 修改标志位，保留物理页号
+    pub fn set_flags(&mut self, flags: Flags) {
+        self.0.set_bits(FLAG_RANGE, flags.bits() as usize);
+    }
     /// 清除
     pub fn clear(&mut self) {
         self.0 = 0;
@@ -103,7 +107,7 @@ bitflags! {
         const EXECUTABLE =  1 << 3;
         /// 用户位
         const USER =        1 << 4;
-        /// 全局位，我们不会使用
+        /// 全局位，用于所有地址空间共享的内核映射，按 ASID 刷新 TLB 时不会被刷新
         const GLOBAL =      1 << 5;
         /// 已使用位，用于替换算法
         const ACCESSED =    1 << 6;
@@ -8,6 +8,10 @@ pub enum MapType {
     Linear,
     /// 按帧分配映射
     Framed,
+    /// 映射到已有的物理页（例如页缓存），由调用者提供，多个映射可以共享同一页
+    Shared,
+    /// 由文件提供内容，页面在第一次访问时从页缓存映射，映射同一文件的进程共享这些页面
+    File,
 }
 // 上层需要做的是把一个 Segment 中没有建立物理页映射关系的全部虚拟页，都申请到物理页并建立映射关系
 
@@ -29,7 +33,7 @@ impl Segment {
             // 线性映射可以直接将虚拟地址转换
             MapType::Linear => Some(self.page_range().into().iter()),
             // 按帧映射无法直接获得物理地址，需要分配
-            MapType::Framed => None,
+            MapType::Framed | MapType::Shared | MapType::File => None,
         }
     }
     /// 将地址相应地上下取整，获得虚拟页号区间
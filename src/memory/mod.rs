@@ -9,9 +9,12 @@
 pub mod address;
 pub mod heap;
 pub mod config;
+pub mod elf;
 pub mod frame;
 pub mod range;
 pub mod mapping;
+pub mod shm;
+pub mod user;
 
 /// 一个缩写，模块中一些函数会使用
 pub type MemoryResult<T> = Result<T, &'static str>;
@@ -19,9 +22,12 @@ pub type MemoryResult<T> = Result<T, &'static str>;
 pub use {
     address::*, 
     config::*, 
+    elf::{ElfError, ElfInfo},
     frame::FRAME_ALLOCATOR, 
     range::Range,
     mapping::{Flags, MapType, MemorySet, Segment},
+    shm::{SharedMemory, ShmID},
+    user::{SumGuard, UserPtr, UserSlice},
 };
 
 
@@ -30,8 +36,6 @@ pub use {
 /// - [`heap::init`]
 pub fn init() {
     heap::init();
-    // 允许内核读写用户态内存
-    unsafe { riscv::register::sstatus::set_sum() };
 
     println!("mod memory initialized.");
 }
@@ -0,0 +1,65 @@
+//! 共享内存 [`SharedMemory`]
+//!
+//! 共享内存是一组物理页，可以同时映射到多个进程的地址空间中，每个映射的权限可以不同。
+//! 创建者和每个映射都持有物理页的引用，所有引用都被释放后物理页才会回收。
+
+use crate::memory::{frame::FrameTracker, *};
+use alloc::{collections::BTreeMap, sync::{Arc, Weak}, vec::Vec};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use lazy_static::*;
+use spin::Mutex;
+
+/// 共享内存 ID
+pub type ShmID = usize;
+
+/// 共享内存计数器，用于分配 ID
+static SHM_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+lazy_static! {
+    /// 所有共享内存，只保存弱引用，不再被持有的共享内存会在创建新的共享内存时清除
+    static ref SHARED_MEMORIES: Mutex<BTreeMap<ShmID, Weak<SharedMemory>>> =
+        Mutex::new(BTreeMap::new());
+}
+
+/// 一段共享内存
+pub struct SharedMemory {
+    /// 共享内存 ID
+    pub id: ShmID,
+    /// 按顺序排列的物理页
+    pub frames: Vec<Arc<FrameTracker>>,
+}
+
+impl SharedMemory {
+    /// 创建共享内存，大小向上取整到页，页面初始为全零
+    pub fn new(size: usize) -> MemoryResult<Arc<Self>> {
+        if size == 0 {
+            return Err("cannot create empty shared memory");
+        }
+        let mut frames = Vec::new();
+        for _ in 0..(size + PAGE_SIZE - 1) / PAGE_SIZE {
+            let mut frame = FRAME_ALLOCATOR.lock().alloc()?;
+            for byte in frame.iter_mut() {
+                *byte = 0;
+            }
+            frames.push(Arc::new(frame));
+        }
+        let shm = Arc::new(Self {
+            id: SHM_COUNTER.fetch_add(1, Ordering::Relaxed) + 1,
+            frames,
+        });
+        let mut shared_memories = SHARED_MEMORIES.lock();
+        shared_memories.retain(|_, shm| shm.strong_count() > 0);
+        shared_memories.insert(shm.id, Arc::downgrade(&shm));
+        Ok(shm)
+    }
+
+    /// 按 ID 找到仍被持有的共享内存
+    pub fn get(id: ShmID) -> Option<Arc<Self>> {
+        SHARED_MEMORIES.lock().get(&id).and_then(Weak::upgrade)
+    }
+
+    /// 共享内存的大小，为整页
+    pub fn size(&self) -> usize {
+        self.frames.len() * PAGE_SIZE
+    }
+}
@@ -0,0 +1,27 @@
+# 拷贝用户内存，出现缺页时由异常修复表跳转到修复代码
+#
+# 每一条可能访问用户内存的指令区间都在 __ex_table 段中登记为
+# （区间起始地址，区间结束地址，修复代码地址）
+
+    .section .text
+    .globl __copy_user
+# __copy_user(dst: *mut u8, src: *const u8, len: usize) -> usize
+# 逐字节拷贝，返回没有拷贝的字节数，即成功时返回 0
+__copy_user:
+    beqz    a2, __copy_user_return
+__copy_user_loop:
+    lb      t0, 0(a1)
+    sb      t0, 0(a0)
+__copy_user_loop_end:
+    addi    a0, a0, 1
+    addi    a1, a1, 1
+    addi    a2, a2, -1
+    bnez    a2, __copy_user_loop
+# 正常结束和出错都从这里返回剩余的字节数
+__copy_user_return:
+    mv      a0, a2
+    ret
+
+    .section __ex_table, "a"
+    .balign 8
+    .dword  __copy_user_loop, __copy_user_loop_end, __copy_user_return
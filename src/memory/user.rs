@@ -0,0 +1,195 @@
+//! 访问用户内存 [`UserPtr`]、[`UserSlice`]
+//!
+//! 系统调用中用户传入的指针不能直接使用：地址可能没有映射、不属于用户或没有相应的权限。
+//! 这里先对照当前进程的 [`MemorySet`] 检查地址区间，再在 [`SumGuard`] 的保护下拷贝数据，
+//! 检查不通过时返回 `Err`，由系统调用转换为 `EFAULT`。
+//!
+//! 检查时还会映射区间中按需加载、尚未映射的文件页面，因为拷贝时持有进程的锁，无法在缺页异常中处理。
+//! 拷贝由 `user.asm` 中的 `__copy_user` 完成，其访存指令登记在异常修复表中，
+//! 即使检查之后页面仍然无法访问，缺页也只会使拷贝返回 `Err`，而不会杀死当前线程。
+//!
+//! 内核平时不打开 `sstatus.SUM`，因此内核不会意外读写用户内存。
+
+use crate::memory::{address::*, mapping::Flags, MemoryResult, MemorySet, Range};
+use alloc::{string::String, vec, vec::Vec};
+use core::marker::PhantomData;
+use core::mem::{size_of, MaybeUninit};
+use riscv::register::sstatus;
+
+global_asm!(include_str!("./user.asm"));
+
+extern "C" {
+    /// `user.asm` 中拷贝用户内存的函数，返回没有拷贝的字节数
+    fn __copy_user(dst: *mut u8, src: *const u8, len: usize) -> usize;
+}
+
+/// 在存在期间允许内核访问用户内存（打开 `sstatus.SUM`），释放时恢复原来的状态
+pub struct SumGuard {
+    /// 创建之前 `SUM` 是否已经打开
+    was_set: bool,
+}
+
+impl SumGuard {
+    /// 打开 `sstatus.SUM`
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        let was_set = sstatus::read().sum();
+        unsafe { sstatus::set_sum() };
+        Self { was_set }
+    }
+}
+
+impl Drop for SumGuard {
+    fn drop(&mut self) {
+        if !self.was_set {
+            unsafe { sstatus::clear_sum() };
+        }
+    }
+}
+
+/// 指向用户内存中一个 `T` 的指针
+#[derive(Clone, Copy)]
+pub struct UserPtr<T> {
+    /// 用户传入的地址
+    address: usize,
+    _marker: PhantomData<T>,
+}
+
+impl<T: Copy> UserPtr<T> {
+    /// 包装用户传入的地址，此时不做检查
+    pub fn new(address: usize) -> Self {
+        Self {
+            address,
+            _marker: PhantomData,
+        }
+    }
+
+    /// 从用户内存读取
+    pub fn read(&self, memory_set: &mut MemorySet) -> MemoryResult<T> {
+        check_user(memory_set, self.address, size_of::<T>(), Flags::READABLE)?;
+        let mut value = MaybeUninit::<T>::uninit();
+        unsafe {
+            copy_user(value.as_mut_ptr() as *mut u8, self.address as *const u8, size_of::<T>())?;
+            Ok(value.assume_init())
+        }
+    }
+
+    /// 写入用户内存
+    pub fn write(&self, memory_set: &mut MemorySet, value: T) -> MemoryResult<()> {
+        check_user(memory_set, self.address, size_of::<T>(), Flags::WRITABLE)?;
+        unsafe {
+            copy_user(self.address as *mut u8, &value as *const T as *const u8, size_of::<T>())
+        }
+    }
+}
+
+/// 用户内存中的一段字节
+#[derive(Clone, Copy)]
+pub struct UserSlice {
+    /// 用户传入的起始地址
+    address: usize,
+    /// 长度
+    len: usize,
+}
+
+impl UserSlice {
+    /// 包装用户传入的地址和长度，此时不做检查
+    pub fn new(address: usize, len: usize) -> Self {
+        Self { address, len }
+    }
+
+    /// 起始地址
+    pub fn address(&self) -> usize {
+        self.address
+    }
+
+    /// 长度
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// 是否为空
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// 检查整段是否可以写入，用于在产生数据之前提前报错
+    pub fn check_writable(&self, memory_set: &mut MemorySet) -> MemoryResult<()> {
+        check_user(memory_set, self.address, self.len, Flags::WRITABLE)
+    }
+
+    /// 将整段拷贝到内核中
+    ///
+    /// 会在内核堆上分配与这段内存等长的缓冲区，调用者需要先限制长度
+    pub fn read(&self, memory_set: &mut MemorySet) -> MemoryResult<Vec<u8>> {
+        let mut data = vec![0; self.len];
+        copy_from_user(memory_set, &mut data, self.address)?;
+        Ok(data)
+    }
+
+    /// 将 `data` 拷贝到这段用户内存的开头，`data` 不能比这段内存长
+    pub fn write(&self, memory_set: &mut MemorySet, data: &[u8]) -> MemoryResult<()> {
+        if data.len() > self.len {
+            return Err("data is longer than user buffer");
+        }
+        copy_to_user(memory_set, self.address, data)
+    }
+}
+
+/// 从用户地址 `src` 拷贝 `dst.len()` 个字节
+pub fn copy_from_user(memory_set: &mut MemorySet, dst: &mut [u8], src: usize) -> MemoryResult<()> {
+    check_user(memory_set, src, dst.len(), Flags::READABLE)?;
+    unsafe { copy_user(dst.as_mut_ptr(), src as *const u8, dst.len()) }
+}
+
+/// 将 `src` 拷贝到用户地址 `dst`
+pub fn copy_to_user(memory_set: &mut MemorySet, dst: usize, src: &[u8]) -> MemoryResult<()> {
+    check_user(memory_set, dst, src.len(), Flags::WRITABLE)?;
+    unsafe { copy_user(dst as *mut u8, src.as_ptr(), src.len()) }
+}
+
+/// 从用户地址读取以 `\0` 结尾的字符串，最多读取 `max_len` 个字节
+///
+/// 不是合法的 utf-8 或超过长度时返回 `Err`
+pub fn read_c_str(memory_set: &mut MemorySet, address: usize, max_len: usize) -> MemoryResult<String> {
+    let mut bytes = Vec::new();
+    loop {
+        if bytes.len() >= max_len {
+            return Err("user string is too long");
+        }
+        let byte = UserPtr::<u8>::new(address + bytes.len()).read(memory_set)?;
+        if byte == 0 {
+            break;
+        }
+        bytes.push(byte);
+    }
+    String::from_utf8(bytes).map_err(|_| "user string is not valid utf-8")
+}
+
+/// 在 [`SumGuard`] 的保护下用 `__copy_user` 拷贝，拷贝途中出现缺页时返回 `Err`
+unsafe fn copy_user(dst: *mut u8, src: *const u8, len: usize) -> MemoryResult<()> {
+    let _sum = SumGuard::new();
+    if __copy_user(dst, src, len) == 0 {
+        Ok(())
+    } else {
+        Err("page fault while accessing user memory")
+    }
+}
+
+/// 检查从 `address` 开始长为 `len` 的区间全部属于具有 `flags` 权限的用户 Segment，并映射其中的文件页面
+///
+/// `memory_set` 不必是当前的地址空间，此时检查之后用 [`MemorySet::write_bytes`] 等访问
+pub fn check_user(memory_set: &mut MemorySet, address: usize, len: usize, flags: Flags) -> MemoryResult<()> {
+    let end = address.checked_add(len).ok_or("user address overflow")?;
+    let range = Range::from(VirtualAddress(address)..VirtualAddress(end));
+    if memory_set.check_user(range, flags) {
+        if len > 0 {
+            memory_set.populate(Range::from(
+                VirtualPageNumber::floor(range.start)..VirtualPageNumber::ceil(range.end),
+            ));
+        }
+        Ok(())
+    } else {
+        Err("bad user address")
+    }
+}
@@ -7,7 +7,7 @@
 use core::panic::PanicInfo;
 use crate::sbi::shutdown; // 导入本项目的sbi.rs的shutdown函数
 
-/// 打印 panic 的信息并 [`shutdown`]
+/// 打印 panic 的信息，写回块缓存并 [`shutdown`]
 ///
 /// ### `#[panic_handler]` 属性
 /// 声明此函数是 panic 的回调
@@ -18,6 +18,10 @@ fn panic_handler(info: &PanicInfo) -> ! {
     //
     // 需要全局开启 feature(panic_info_message) 才可以调用 .message() 函数
     println!("\x1b[1;31mpanic: '{}'\x1b[0m", info.message().unwrap());
+    // 尽量将块缓存中尚未写回的数据写入设备
+    if !crate::fs::try_flush_block_caches() {
+        println!("\x1b[1;31mfailed to flush block caches\x1b[0m");
+    }
     shutdown()
 }
 
@@ -3,5 +3,8 @@
 /// 每个线程的运行栈大小 512 KB
 pub const STACK_SIZE: usize = 0x8_0000;
 
+/// 为线程栈和 mmap 查找空闲虚拟地址时的默认起点
+pub const MMAP_BASE: usize = 0x100_0000;
+
 /// 共用的内核栈大小 512 KB
 pub const KERNEL_STACK_SIZE: usize = 0x8_0000;
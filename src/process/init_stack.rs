@@ -0,0 +1,143 @@
+//! 用户进程的初始栈 [`InitInfo`]
+//!
+//! 按照 System V ABI，在第一个线程的栈顶放置（从高地址到低地址）：
+//! - `AT_RANDOM` 指向的 16 个随机字节、参数和环境变量字符串，以及需要时程序头的拷贝
+//! - 对齐到 16 字节之后，依次为 argc、argv（以 0 结尾）、envp（以 0 结尾）和辅助向量（以 `AT_NULL` 结尾）
+//!
+//! 线程开始执行时 `sp` 指向 argc，标准的 C 和 Rust 运行时可以直接从这里取得参数。
+
+use super::*;
+use crate::fs::fill_random;
+use alloc::{collections::BTreeMap, string::String, vec};
+use core::mem::size_of;
+use xmas_elf::ElfFile;
+
+/// 辅助向量的结尾
+pub const AT_NULL: usize = 0;
+/// 程序头的地址
+pub const AT_PHDR: usize = 3;
+/// 每个程序头的大小
+pub const AT_PHENT: usize = 4;
+/// 程序头的个数
+pub const AT_PHNUM: usize = 5;
+/// 页面大小
+pub const AT_PAGESZ: usize = 6;
+/// 解释器的加载地址
+pub const AT_BASE: usize = 7;
+/// 程序的入口地址
+pub const AT_ENTRY: usize = 9;
+/// 16 个随机字节的地址
+pub const AT_RANDOM: usize = 25;
+
+/// 初始栈中的内容
+#[derive(Default)]
+pub struct InitInfo {
+    /// 参数，第一个通常是程序的路径
+    pub args: Vec<String>,
+    /// 环境变量，形如 `KEY=VALUE`
+    pub envs: Vec<String>,
+    /// 辅助向量，`AT_RANDOM` 以及拷贝到栈上的 `AT_PHDR` 由 [`InitInfo::push_at`] 填写
+    pub auxv: BTreeMap<usize, usize>,
+    /// 程序头不在加载的段中时，需要拷贝到栈上的程序头
+    pub program_headers: Vec<u8>,
+}
+
+impl InitInfo {
+    /// 根据 ELF 的加载信息填写辅助向量
+    pub fn new(args: Vec<String>, envs: Vec<String>, elf: &ElfFile, info: &ElfInfo) -> Self {
+        let mut auxv = BTreeMap::new();
+        auxv.insert(AT_PHENT, info.phent);
+        auxv.insert(AT_PHNUM, info.phnum);
+        auxv.insert(AT_PAGESZ, PAGE_SIZE);
+        auxv.insert(AT_ENTRY, info.entry.0);
+        if let Some(base) = info.interp_base {
+            auxv.insert(AT_BASE, base.0);
+        }
+        let program_headers = match info.phdr {
+            Some(phdr) => {
+                auxv.insert(AT_PHDR, phdr.0);
+                Vec::new()
+            }
+            None => {
+                let offset = elf.header.pt2.ph_offset() as usize;
+                elf.input
+                    .get(offset..offset + info.phent * info.phnum)
+                    .map(|headers| headers.to_vec())
+                    .unwrap_or_default()
+            }
+        };
+        Self {
+            args,
+            envs,
+            auxv,
+            program_headers,
+        }
+    }
+
+    /// 将内容写入 `memory_set` 中以 `stack_top` 为栈顶、大小为 `stack_size` 的栈，返回线程开始时的 `sp`
+    pub fn push_at(
+        &self,
+        memory_set: &MemorySet,
+        stack_top: VirtualAddress,
+        stack_size: usize,
+    ) -> MemoryResult<VirtualAddress> {
+        let mut auxv = self.auxv.clone();
+        let strings_size: usize = self
+            .args
+            .iter()
+            .chain(self.envs.iter())
+            .map(|string| string.len() + 1)
+            .sum();
+        let area_size = self.program_headers.len() + strings_size + 16;
+        if area_size > stack_size {
+            return Err("arguments are too long");
+        }
+
+        // 栈顶的数据区：程序头、字符串、随机字节，程序头按 8 字节对齐
+        let area_start = (stack_top.0 - area_size) & !(size_of::<usize>() - 1);
+        let mut area = vec![0u8; stack_top.0 - area_start];
+        let mut position = 0;
+        if !self.program_headers.is_empty() {
+            area[..self.program_headers.len()].copy_from_slice(&self.program_headers);
+            auxv.insert(AT_PHDR, area_start);
+            position += self.program_headers.len();
+        }
+        let mut push_string = |string: &String| {
+            let address = area_start + position;
+            area[position..position + string.len()].copy_from_slice(string.as_bytes());
+            position += string.len() + 1;
+            address
+        };
+        let argv: Vec<usize> = self.args.iter().map(&mut push_string).collect();
+        let envp: Vec<usize> = self.envs.iter().map(&mut push_string).collect();
+        let random_offset = area.len() - 16;
+        fill_random(&mut area[random_offset..]);
+        auxv.insert(AT_RANDOM, area_start + random_offset);
+
+        // argc、argv、envp 和辅助向量
+        let mut words = vec![argv.len()];
+        words.extend(argv);
+        words.push(0);
+        words.extend(envp);
+        words.push(0);
+        for (&key, &value) in auxv.iter() {
+            words.push(key);
+            words.push(value);
+        }
+        words.push(AT_NULL);
+        words.push(0);
+
+        // sp 按 16 字节对齐
+        let sp = (area_start - words.len() * size_of::<usize>()) & !0xf;
+        if stack_top.0 - sp > stack_size {
+            return Err("arguments are too long");
+        }
+        let mut bytes = Vec::with_capacity(words.len() * size_of::<usize>());
+        for word in words {
+            bytes.extend_from_slice(&word.to_le_bytes());
+        }
+        memory_set.write_bytes(VirtualAddress(sp), &bytes)?;
+        memory_set.write_bytes(VirtualAddress(area_start), &area)?;
+        Ok(VirtualAddress(sp))
+    }
+}
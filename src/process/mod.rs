@@ -1,6 +1,7 @@
 //! 管理进程 / 线程
 
 mod config;
+mod init_stack;
 mod lock;
 #[allow(clippy::module_inception)]
 mod process;
@@ -10,12 +11,13 @@ mod kernel_stack;
 
 use crate::interrupt::*;
 use crate::memory::*;
-use alloc::{sync::Arc};
+use alloc::{sync::Arc, vec::Vec};
 use spin::Mutex;
 
 pub use config::*;
+pub use init_stack::InitInfo;
 pub use kernel_stack::KERNEL_STACK;
 pub use lock::Lock;
-pub use process::Process;
-pub use processor::PROCESSOR;
+pub use process::{MmapSource, Process, ProcessID, SyscallAbi, INIT_PID, PROCESS_TABLE};
+pub use processor::{live_threads, PROCESSOR};
 pub use thread::Thread;
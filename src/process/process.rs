@@ -2,49 +2,430 @@
 // 在我们实现的简单操作系统中，进程只需要维护页面映射，并且存储一点额外信息
 // 
 use super::*;
+use crate::error::{KernelError, KernelResult};
 use crate::fs::*;
+use crate::kernel::Condvar;
 use xmas_elf::ElfFile;
-use alloc::{vec, vec::Vec};
+use alloc::{collections::BTreeMap, sync::Weak, vec, vec::Vec};
+use core::cmp::{max, min};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use lazy_static::lazy_static;
+
+/// 进程 ID
+pub type ProcessID = usize;
+
+/// 第一个用户进程 init 的 ID，结束的进程的子进程交由它回收
+pub const INIT_PID: ProcessID = 1;
+
+/// 进程计数器，用于分配进程 ID，[`INIT_PID`] 保留给 init
+static PROCESS_COUNTER: AtomicUsize = AtomicUsize::new(INIT_PID);
+
+lazy_static! {
+    /// 所有存活的进程，进程被释放时会从表中移除
+    ///
+    /// 进程可能在中断处理中被释放，所以使用关闭中断的 [`Lock`]
+    pub static ref PROCESS_TABLE: Lock<BTreeMap<ProcessID, Weak<Process>>> =
+        Lock::new(BTreeMap::new());
+}
+
+/// 进程使用的系统调用接口
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SyscallAbi {
+    /// 本内核自己的系统调用编号，见 `kernel/syscall.rs`
+    Native,
+    /// Linux riscv64 的系统调用编号和约定，用于运行不经修改的 Linux 静态程序
+    Linux,
+}
 
 /// 进程的信息
 pub struct Process {
+    /// 进程 ID
+    pub pid: ProcessID,
     /// 是否属于用户态
     pub is_user: bool, // 用户态标识：我们会在后面进行区分内核态线程和用户态线程。
+    /// 系统调用接口，子进程和 `execve` 后的程序沿用
+    pub abi: SyscallAbi,
     /// 用 `Mutex` 包装一些可变的变量
     pub inner: Mutex<ProcessInner>, // 进程也需要一部分是可变的。
+    /// 子进程结束时唤醒等待的线程
+    pub child_exit: Condvar,
 }
 
 pub struct ProcessInner {
     /// 进程中的线程公用页表 / 内存映射
     pub memory_set: MemorySet, // 访存空间. ：进程中的线程会共享同一个页表，即可以访问的虚拟内存空间
-    // 打开的文件描述符
-    pub descriptors: Vec<Arc<dyn INode>>,
+    /// 打开的文件描述符，关闭的描述符为 `None`，之后打开的文件会重新使用
+    pub descriptors: Vec<Option<Arc<FileHandle>>>,
+    /// 进程中的线程，只用于统计，不影响线程的生命周期
+    pub threads: Vec<Weak<Thread>>,
+    /// 以共享方式映射的文件区间
+    pub file_mappings: Vec<FileMapping>,
+    /// 进程创建的共享内存，进程结束前不会被释放
+    pub shared_memories: Vec<Arc<SharedMemory>>,
+    /// 映射的共享内存
+    pub shm_attachments: Vec<ShmAttachment>,
+    /// 程序断点，即堆的结束地址，从 [`MemorySet::heap_start`] 开始
+    pub brk: VirtualAddress,
+    /// 线程栈的权限，ELF 中的 `PT_GNU_STACK` 可以使栈可执行
+    pub stack_flags: Flags,
+    /// 父进程，父进程先结束时为空
+    pub parent: Weak<Process>,
+    /// 尚未被回收的子进程，包括已经结束的
+    pub children: Vec<Arc<Process>>,
+    /// 进程结束时的退出码，进程仍在运行时为 `None`
+    pub exit_code: Option<i32>,
+}
+
+/// 内存映射的来源
+pub enum MmapSource {
+    /// 匿名映射，页面初始为全零
+    Anonymous,
+    /// 私有文件映射，页面是文件从给定偏移开始的内容的拷贝
+    PrivateFile(Arc<dyn INode>, usize),
+    /// 共享文件映射，直接映射页缓存中的页面，修改会在解除映射时写回文件
+    SharedFile(Arc<dyn INode>, usize),
+    /// 共享内存，映射其中从头开始的页面
+    SharedMemory(Arc<SharedMemory>),
+}
+
+/// 以共享方式映射的文件区间，解除映射时需要写回文件
+#[derive(Clone)]
+pub struct FileMapping {
+    /// 映射的虚拟页
+    pub range: Range<VirtualPageNumber>,
+    /// 映射的文件
+    pub inode: Arc<dyn INode>,
+    /// 区间起始处对应的文件页号
+    pub first_page: usize,
+}
+
+/// 映射到进程中的一段共享内存
+#[derive(Clone)]
+pub struct ShmAttachment {
+    /// 映射的虚拟页
+    pub range: Range<VirtualPageNumber>,
+    /// 映射的共享内存
+    pub shm: Arc<SharedMemory>,
+}
+
+impl FileMapping {
+    /// 将区间中 `range` 部分的页面写回文件
+    fn write_back(&self, range: Range<VirtualPageNumber>) {
+        for vpn in range.iter() {
+            let index = self.first_page + (vpn - self.range.start);
+            if PAGE_CACHE.write_back(&self.inode, index).is_err() {
+                println!("failed to write back mapped page {}", vpn);
+            }
+        }
+    }
+}
+
+impl ProcessInner {
+    /// 文件描述符对应的打开的文件
+    pub fn file(&self, fd: usize) -> Option<Arc<FileHandle>> {
+        self.descriptors.get(fd).cloned().flatten()
+    }
+
+    /// 加入打开的文件，使用最小的空闲描述符
+    pub fn add_file(&mut self, file: Arc<FileHandle>) -> usize {
+        match self.descriptors.iter().position(Option::is_none) {
+            Some(fd) => {
+                self.descriptors[fd] = Some(file);
+                fd
+            }
+            None => {
+                self.descriptors.push(Some(file));
+                self.descriptors.len() - 1
+            }
+        }
+    }
+
+    /// 关闭文件描述符，描述符不存在时返回 `false`
+    pub fn close_file(&mut self, fd: usize) -> bool {
+        match self.descriptors.get_mut(fd) {
+            Some(file @ Some(_)) => {
+                *file = None;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// 解除一段虚拟页的映射，其中共享映射的文件页面会先写回文件
+    fn unmap_range(&mut self, range: Range<VirtualPageNumber>) -> MemoryResult<()> {
+        let mut remaining = Vec::new();
+        for mapping in self.file_mappings.drain(..) {
+            if !mapping.range.overlap_with(&range) {
+                remaining.push(mapping);
+                continue;
+            }
+            let start = max(mapping.range.start, range.start);
+            let end = min(mapping.range.end, range.end);
+            mapping.write_back(Range::from(start..end));
+            // 保留区间之外的部分
+            if mapping.range.start < start {
+                remaining.push(FileMapping {
+                    range: Range::from(mapping.range.start..start),
+                    inode: mapping.inode.clone(),
+                    first_page: mapping.first_page,
+                });
+            }
+            if end < mapping.range.end {
+                remaining.push(FileMapping {
+                    range: Range::from(end..mapping.range.end),
+                    inode: mapping.inode.clone(),
+                    first_page: mapping.first_page + (end - mapping.range.start),
+                });
+            }
+        }
+        self.file_mappings = remaining;
+        // 部分解除映射的共享内存不能再整体解除，剩余的页面只能通过 munmap 解除
+        self.shm_attachments
+            .retain(|attachment| !attachment.range.overlap_with(&range));
+        self.memory_set.remove_range(range)
+    }
 }
 
 #[allow(unused)]
 impl Process {
     /// 创建一个内核进程, 只能创建一个内核进程！！！
     pub fn new_kernel() -> MemoryResult<Arc<Self>> {
-        Ok(Arc::new(Self {
+        Ok(Self::register(Self {
+            pid: Self::alloc_pid(),
             is_user: false,
+            abi: SyscallAbi::Native,
             inner: Mutex::new(ProcessInner {
                 memory_set: MemorySet::new_kernel()?,
-                descriptors: vec![STDIN.clone(), STDOUT.clone()], // 目前只支持打开STDIN和STDOUT
+                descriptors: standard_descriptors(SyscallAbi::Native), // 目前只支持打开STDIN和STDOUT
+                threads: Vec::new(),
+                file_mappings: Vec::new(),
+                shared_memories: Vec::new(),
+                shm_attachments: Vec::new(),
+                brk: VirtualAddress(0),
+                stack_flags: Flags::READABLE | Flags::WRITABLE,
+                parent: Weak::new(),
+                children: Vec::new(),
+                exit_code: None,
             }),
+            child_exit: Condvar::default(),
         }))
     }
 
     /// 创建进程，从文件中读取代码, 用户进程根据文件创建
-    pub fn from_elf(file: &ElfFile, is_user: bool) -> MemoryResult<Arc<Self>> {
-        Ok(Arc::new(Self {
+    ///
+    /// 同时返回入口地址等加载信息。`abi` 决定进程使用的系统调用接口，
+    /// `pid` 通常由 [`Process::alloc_pid`] 分配，创建 init 时为 [`INIT_PID`]
+    pub fn from_elf(
+        inode: &Arc<dyn INode>,
+        file: &ElfFile,
+        is_user: bool,
+        abi: SyscallAbi,
+        pid: ProcessID,
+    ) -> Result<(Arc<Self>, ElfInfo), ElfError> {
+        let (memory_set, info) = MemorySet::from_elf(inode, file, is_user)?;
+        let brk = memory_set.heap_start;
+        let stack_flags =
+            Flags::READABLE | Flags::WRITABLE | Flags::executable(info.executable_stack);
+        let process = Self::register(Self {
+            pid,
             is_user,
+            abi,
             inner: Mutex::new(ProcessInner {
-                memory_set: MemorySet::from_elf(file, is_user)?,
-                descriptors: vec![STDIN.clone(), STDOUT.clone()],
+                memory_set,
+                descriptors: standard_descriptors(abi),
+                threads: Vec::new(),
+                file_mappings: Vec::new(),
+                shared_memories: Vec::new(),
+                shm_attachments: Vec::new(),
+                brk,
+                stack_flags,
+                parent: Weak::new(),
+                children: Vec::new(),
+                exit_code: None,
             }),
+            child_exit: Condvar::default(),
+        });
+        Ok((process, info))
+    }
+
+    /// 复制出一个子进程，地址空间是当前进程的拷贝，打开的文件与当前进程共享
+    ///
+    /// 子进程中还没有线程，需要由调用者创建
+    pub fn fork(self: &Arc<Self>) -> MemoryResult<Arc<Self>> {
+        let child = {
+            let inner = self.inner();
+            Self::register(Self {
+                pid: Self::alloc_pid(),
+                is_user: self.is_user,
+                abi: self.abi,
+                inner: Mutex::new(ProcessInner {
+                    memory_set: inner.memory_set.fork()?,
+                    descriptors: inner.descriptors.clone(),
+                    threads: Vec::new(),
+                    file_mappings: inner.file_mappings.clone(),
+                    // 共享内存仍然由创建它的进程持有
+                    shared_memories: Vec::new(),
+                    shm_attachments: inner.shm_attachments.clone(),
+                    brk: inner.brk,
+                    stack_flags: inner.stack_flags,
+                    parent: Arc::downgrade(self),
+                    children: Vec::new(),
+                    exit_code: None,
+                }),
+                child_exit: Condvar::default(),
+            })
+        };
+        self.inner().children.push(child.clone());
+        Ok(child)
+    }
+
+    /// 用新加载的程序替换进程的地址空间，用于 `execve`
+    ///
+    /// 除 `current` 以外的线程全部结束，共享映射的文件页面先写回。
+    /// 所有线程原先的栈随旧的地址空间一起释放，调用者需要为 `current` 重新分配栈
+    pub fn exec(&self, current: &Thread, memory_set: MemorySet, info: &ElfInfo) {
+        self.kill_threads(Some(current));
+        // 被结束的线程稍后才被释放，此时不能再从新的地址空间中移除它们的栈
+        for thread in self.threads() {
+            let mut thread = thread.inner();
+            let start = thread.stack.start;
+            thread.stack = Range::from(start..start);
+        }
+        let mut inner = self.inner();
+        for mapping in inner.file_mappings.drain(..) {
+            mapping.write_back(mapping.range);
+        }
+        inner.shm_attachments.clear();
+        inner.brk = memory_set.heap_start;
+        inner.stack_flags =
+            Flags::READABLE | Flags::WRITABLE | Flags::executable(info.executable_stack);
+        // 先换上新的页表，再释放旧的地址空间
+        let old = core::mem::replace(&mut inner.memory_set, memory_set);
+        inner.memory_set.activate();
+        drop(old);
+    }
+
+    /// 结束进程：记录退出码，结束所有线程并关闭文件，然后通知父进程
+    ///
+    /// 已经结束的进程由父进程通过 [`Process::wait_child`] 回收。
+    /// 子进程交给 init 回收，没有 init 或 init 自身结束时随线程一起释放
+    pub fn exit(&self, code: i32) {
+        self.kill_threads(None);
+        let (children, parent) = {
+            let mut inner = self.inner();
+            inner.exit_code = Some(code);
+            inner.descriptors.clear();
+            let children: Vec<_> = inner.children.drain(..).collect();
+            (children, inner.parent.upgrade())
+        };
+        let init = Self::get(INIT_PID).filter(|init| init.pid != self.pid);
+        for child in children.iter() {
+            child.inner().parent = init.as_ref().map_or_else(Weak::new, Arc::downgrade);
+        }
+        if let Some(init) = init {
+            if !children.is_empty() {
+                init.inner().children.extend(children);
+                // 交给 init 的子进程可能已经结束
+                init.child_exit.notify_all();
+            }
+        }
+        if let Some(parent) = parent {
+            parent.child_exit.notify_all();
+        }
+    }
+
+    /// 结束进程中的一个线程，所有线程都结束时进程以 `code` 结束
+    pub fn exit_thread(&self, thread: &Thread, code: i32) {
+        thread.inner().dead = true;
+        let exited = self.inner().exit_code.is_some();
+        if !exited && self.threads().iter().all(|thread| thread.inner().dead) {
+            self.exit(code);
+        }
+    }
+
+    /// 回收一个已经结束的子进程，`pid` 为 `None` 时可以是任意子进程
+    ///
+    /// 返回子进程的 ID 和退出码；符合条件的子进程都还没有结束时返回 `Ok(None)`
+    pub fn wait_child(&self, pid: Option<ProcessID>) -> KernelResult<Option<(ProcessID, i32)>> {
+        let mut inner = self.inner();
+        let matches = |child: &Arc<Process>| pid.map_or(true, |pid| pid == child.pid);
+        if !inner.children.iter().any(matches) {
+            return Err(KernelError::NoChild);
+        }
+        let exited = inner
+            .children
+            .iter()
+            .position(|child| matches(child) && child.inner().exit_code.is_some());
+        Ok(exited.map(|i| {
+            let child = inner.children.remove(i);
+            let code = child.inner().exit_code.unwrap();
+            (child.pid, code)
         }))
     }
 
+    /// 将进程中的线程标记为结束，`keep` 除外
+    ///
+    /// 休眠的线程会被唤醒，由 [`PROCESSOR`] 在调度到它们时移除
+    fn kill_threads(&self, keep: Option<&Thread>) {
+        for thread in self.threads() {
+            if keep.map_or(false, |keep| keep.id == thread.id) {
+                continue;
+            }
+            let sleeping = {
+                let mut inner = thread.inner();
+                inner.dead = true;
+                inner.sleeping
+            };
+            if sleeping {
+                PROCESSOR.lock().wake_thread(thread);
+            }
+        }
+    }
+
+    /// 分配一个新的进程 ID
+    pub fn alloc_pid() -> ProcessID {
+        PROCESS_COUNTER.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    /// 将进程加入 [`static@PROCESS_TABLE`]
+    fn register(process: Self) -> Arc<Self> {
+        let process = Arc::new(process);
+        PROCESS_TABLE
+            .lock()
+            .insert(process.pid, Arc::downgrade(&process));
+        process
+    }
+
+    /// 按进程 ID 找到存活的进程
+    pub fn get(pid: ProcessID) -> Option<Arc<Self>> {
+        PROCESS_TABLE.lock().get(&pid).and_then(Weak::upgrade)
+    }
+
+    /// 所有存活的进程，按进程 ID 排序
+    pub fn all() -> Vec<Arc<Self>> {
+        PROCESS_TABLE
+            .lock()
+            .values()
+            .filter_map(Weak::upgrade)
+            .collect()
+    }
+
+    /// 记录进程中新建的线程，同时清理已经释放的线程
+    pub fn add_thread(&self, thread: &Arc<Thread>) {
+        let threads = &mut self.inner().threads;
+        threads.retain(|thread| thread.strong_count() > 0);
+        threads.push(Arc::downgrade(thread));
+    }
+
+    /// 进程中所有存活的线程
+    pub fn threads(&self) -> Vec<Arc<Thread>> {
+        self.inner()
+            .threads
+            .iter()
+            .filter_map(Weak::upgrade)
+            .collect()
+    }
+
     /// 上锁并获得可变部分的引用
     pub fn inner(&self) -> spin::MutexGuard<ProcessInner> {
         self.inner.lock()
@@ -62,14 +443,10 @@ impl Process {
     ) -> MemoryResult<Range<VirtualAddress>> {
         let memory_set = &mut self.inner().memory_set;
 
-        // memory_set 只能按页分配，所以让 size 向上取整页
-        let alloc_size = (size + PAGE_SIZE - 1) & !(PAGE_SIZE - 1);
-        // 从 memory_set 中找一段不会发生重叠的空间
-        let mut range = Range::<VirtualAddress>::from(0x1000000..0x1000000 + alloc_size);
-        while memory_set.overlap_with(range.into()) {
-            range.start += alloc_size;
-            range.end += alloc_size;
-        }
+        // 从 memory_set 中找一段不会发生重叠的空间，memory_set 只能按页分配，所以区间会向上取整页
+        let range = memory_set
+            .find_free_range(size, VirtualAddress(MMAP_BASE))
+            .ok_or("no free virtual address space")?;
         // 分配物理页面，建立映射
         memory_set.add_segment(
             Segment {
@@ -79,7 +456,245 @@ impl Process {
             },
             None,
         )?;
-        // 返回地址区间（使用参数 size，而非向上取整的 alloc_size）
+        // 返回地址区间（使用参数 size，而非向上取整的区间大小）
         Ok(Range::from(range.start..(range.start + size)))
     }
+
+    /// 映射一段内存，返回映射的地址区间
+    ///
+    /// `fixed` 为 `true` 时必须映射在 `hint` 处，并替换掉其中原有的映射；
+    /// 否则从 `hint`（为 0 时从 [`MMAP_BASE`]）开始查找空闲的虚拟地址。
+    /// `flags` 只需包括 rwx 权限，user 位会根据进程而定。
+    pub fn mmap(
+        &self,
+        hint: VirtualAddress,
+        size: usize,
+        flags: Flags,
+        fixed: bool,
+        source: MmapSource,
+    ) -> KernelResult<Range<VirtualAddress>> {
+        if size == 0 {
+            return Err(KernelError::InvalidArgument);
+        }
+        let alloc_size = size
+            .checked_add(PAGE_SIZE - 1)
+            .ok_or(KernelError::InvalidArgument)?
+            & !(PAGE_SIZE - 1);
+        if alloc_size > USER_END_ADDRESS.0 {
+            return Err(KernelError::Memory("mapping is too large"));
+        }
+        if let MmapSource::SharedMemory(shm) = &source {
+            if alloc_size > shm.size() {
+                return Err(KernelError::InvalidArgument);
+            }
+        }
+        let flags = flags | Flags::user(self.is_user);
+        // 可能失败的检查和文件读取在修改地址空间之前完成，这样 `fixed` 的映射失败时原有的映射仍然保留。
+        // 之后只有物理页不足或者读取不经过页缓存的文件出错时才会失败
+        mapping::Mapping::check_leaf_flags(flags)?;
+        if let MmapSource::SharedFile(inode, _) = &source {
+            // 可写的共享映射会修改缓存页，正在运行的程序也映射了它们
+            if flags.contains(Flags::WRITABLE) && PAGE_CACHE.write_denied(inode) {
+                return Err(KernelError::TextBusy);
+            }
+        }
+        let file_pages = match &source {
+            MmapSource::SharedFile(inode, offset) => {
+                Some(Self::file_pages(inode, *offset, alloc_size / PAGE_SIZE)?)
+            }
+            MmapSource::PrivateFile(inode, offset) if is_cacheable(inode) => {
+                Some(Self::file_pages(inode, *offset, alloc_size / PAGE_SIZE)?)
+            }
+            _ => None,
+        };
+        let mut inner = self.inner();
+        let range = if fixed {
+            let end = hint.0.checked_add(alloc_size);
+            if hint.page_offset() != 0 || end.map_or(true, |end| end > USER_END_ADDRESS.0) {
+                return Err(KernelError::InvalidArgument);
+            }
+            let range = Range::from(hint..hint + alloc_size);
+            inner.unmap_range(page_range(range))?;
+            range
+        } else {
+            let hint = if hint.valid() { hint } else { VirtualAddress(MMAP_BASE) };
+            inner
+                .memory_set
+                .find_free_range(alloc_size, hint)
+                .ok_or("no free virtual address space")?
+        };
+        let segment = Segment {
+            map_type: MapType::Framed,
+            range,
+            flags,
+        };
+        match source {
+            MmapSource::Anonymous => inner.memory_set.add_segment(segment, None)?,
+            MmapSource::PrivateFile(inode, offset) => {
+                inner.memory_set.add_segment(segment, None)?;
+                // 将文件内容逐页拷贝到新分配的页面中，超出文件结尾的部分保持为 0
+                for (i, vpn) in segment.page_range().iter().enumerate() {
+                    let page = &mut inner.memory_set.mapping.translate(vpn).unwrap().deref_kernel()[..];
+                    if let Some(frames) = &file_pages {
+                        page.copy_from_slice(&frames[i][..]);
+                    } else if let Err(error) = inode.read_at(offset + i * PAGE_SIZE, page) {
+                        inner.memory_set.remove_range(segment.page_range())?;
+                        return Err(error.into());
+                    }
+                }
+            }
+            MmapSource::SharedFile(inode, offset) => {
+                let first_page = offset / PAGE_SIZE;
+                let frames = file_pages.unwrap();
+                let segment = Segment {
+                    map_type: MapType::Shared,
+                    ..segment
+                };
+                inner.memory_set.add_shared_segment(segment, frames)?;
+                inner.file_mappings.push(FileMapping {
+                    range: segment.page_range(),
+                    inode,
+                    first_page,
+                });
+            }
+            MmapSource::SharedMemory(shm) => {
+                let frames = shm.frames[..segment.page_range().len()].to_vec();
+                let segment = Segment {
+                    map_type: MapType::Shared,
+                    ..segment
+                };
+                inner.memory_set.add_shared_segment(segment, frames)?;
+                inner.shm_attachments.push(ShmAttachment {
+                    range: segment.page_range(),
+                    shm,
+                });
+            }
+        }
+        Ok(Range::from(range.start..range.start + size))
+    }
+
+    /// 从页缓存中取得文件从 `offset` 开始的 `pages` 页
+    fn file_pages(
+        inode: &Arc<dyn INode>,
+        offset: usize,
+        pages: usize,
+    ) -> Result<Vec<Arc<frame::FrameTracker>>> {
+        let first_page = offset / PAGE_SIZE;
+        (0..pages)
+            .map(|i| PAGE_CACHE.get_page(inode, first_page + i))
+            .collect()
+    }
+
+    /// 解除一段内存的映射，可以只解除某个映射的一部分
+    ///
+    /// 区间中没有映射的部分会被忽略
+    pub fn munmap(&self, range: Range<VirtualAddress>) -> KernelResult<()> {
+        if range.start.page_offset() != 0 || range.end > USER_END_ADDRESS {
+            return Err(KernelError::InvalidArgument);
+        }
+        Ok(self.inner().unmap_range(page_range(range))?)
+    }
+
+    /// 创建共享内存，由当前进程持有直到进程结束
+    pub fn shm_create(&self, size: usize) -> KernelResult<ShmID> {
+        let shm = SharedMemory::new(size)?;
+        let id = shm.id;
+        self.inner().shared_memories.push(shm);
+        Ok(id)
+    }
+
+    /// 将共享内存整体映射到进程中，返回映射的地址区间
+    ///
+    /// `hint` 和 `flags` 的含义与 [`Process::mmap`] 相同
+    pub fn shm_attach(
+        &self,
+        id: ShmID,
+        hint: VirtualAddress,
+        flags: Flags,
+    ) -> KernelResult<Range<VirtualAddress>> {
+        let shm = SharedMemory::get(id).ok_or(KernelError::InvalidArgument)?;
+        self.mmap(hint, shm.size(), flags, false, MmapSource::SharedMemory(shm))
+    }
+
+    /// 解除从 `address` 开始的共享内存映射
+    pub fn shm_detach(&self, address: VirtualAddress) -> KernelResult<()> {
+        let mut inner = self.inner();
+        let range = inner
+            .shm_attachments
+            .iter()
+            .find(|attachment| VirtualAddress::from(attachment.range.start) == address)
+            .map(|attachment| attachment.range)
+            .ok_or(KernelError::InvalidArgument)?;
+        Ok(inner.unmap_range(range)?)
+    }
+
+    /// 修改一段内存的权限，区间必须全部已经映射
+    ///
+    /// `flags` 只需包括 rwx 权限，user 位会根据进程而定。
+    pub fn mprotect(&self, range: Range<VirtualAddress>, flags: Flags) -> KernelResult<()> {
+        if range.start.page_offset() != 0 || range.end > USER_END_ADDRESS {
+            return Err(KernelError::InvalidArgument);
+        }
+        let range = page_range(range);
+        let mut inner = self.inner();
+        if !inner.memory_set.covers(range) {
+            return Err(KernelError::Memory("address range is not mapped"));
+        }
+        // 与 mmap 相同，正在运行的程序映射的文件不能通过共享映射修改
+        let text_busy = inner.file_mappings.iter().any(|mapping| {
+            mapping.range.overlap_with(&range) && PAGE_CACHE.write_denied(&mapping.inode)
+        });
+        if flags.contains(Flags::WRITABLE) && text_busy {
+            return Err(KernelError::TextBusy);
+        }
+        Ok(inner.memory_set.protect(range, flags | Flags::user(self.is_user))?)
+    }
+
+    /// 将程序断点调整到 `new_brk`，堆所在的 `Framed` 段随之扩大或缩小
+    ///
+    /// 返回调整后的断点。`new_brk` 不合法或无法映射时不做修改，返回当前的断点
+    pub fn brk(&self, new_brk: VirtualAddress) -> VirtualAddress {
+        let mut inner = self.inner();
+        let heap_start = inner.memory_set.heap_start;
+        // 内核进程没有堆
+        if heap_start.0 == 0 || new_brk < heap_start || new_brk > USER_END_ADDRESS {
+            return inner.brk;
+        }
+        let flags = Flags::READABLE | Flags::WRITABLE | Flags::user(self.is_user);
+        if inner
+            .memory_set
+            .resize_framed_segment(heap_start, new_brk, flags)
+            .is_ok()
+        {
+            inner.brk = new_brk;
+        }
+        inner.brk
+    }
+}
+
+/// 进程释放时从 [`static@PROCESS_TABLE`] 中移除，并将共享映射的文件页面写回
+impl Drop for Process {
+    fn drop(&mut self) {
+        PROCESS_TABLE.lock().remove(&self.pid);
+        for mapping in self.inner.get_mut().file_mappings.iter() {
+            mapping.write_back(mapping.range);
+        }
+    }
+}
+
+/// 新进程打开的标准输入输出，Linux 程序还需要标准错误
+fn standard_descriptors(abi: SyscallAbi) -> Vec<Option<Arc<FileHandle>>> {
+    let mut descriptors = vec![
+        Some(FileHandle::new(STDIN.clone())),
+        Some(FileHandle::new(STDOUT.clone())),
+    ];
+    if abi == SyscallAbi::Linux {
+        descriptors.push(Some(FileHandle::new(STDOUT.clone())));
+    }
+    descriptors
+}
+
+/// 地址区间所覆盖的虚拟页
+fn page_range(range: Range<VirtualAddress>) -> Range<VirtualPageNumber> {
+    Range::from(VirtualPageNumber::floor(range.start)..VirtualPageNumber::ceil(range.end))
 }
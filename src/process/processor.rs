@@ -3,7 +3,9 @@
 // 同时，也需要存放和管理目前正在执行的线程（即中断前执行的线程，因为操作系统在工作时是处于中断、异常或系统调用服务之中）
 
 use super::*;
+use crate::memory::mapping::PAGE_TABLE_FRAMES;
 use algorithm::*;
+use core::sync::atomic::Ordering;
 use hashbrown::HashSet;
 use lazy_static::*;
 
@@ -22,6 +24,15 @@ lazy_static! {
     ).unwrap();
 }
 
+/// 除空闲线程以外所有存活的线程，包括正在执行、等待调度和休眠的线程
+pub fn live_threads() -> Vec<Arc<Thread>> {
+    Process::all()
+        .iter()
+        .flat_map(|process| process.threads())
+        .filter(|thread| !Arc::ptr_eq(thread, &IDLE_THREAD))
+        .collect()
+}
+
 /// 不断让 CPU 进入休眠, 等待下一次中断, 是一个死循环
 unsafe fn wait_for_interrupt() {
     loop {
@@ -79,10 +90,15 @@ impl Processor {
     /// 激活下一个线程的 `Context`
     /// 在一个时钟中断时，替换掉 context
     pub fn prepare_next_thread(&mut self) -> *mut Context {
-        // 向调度器询问下一个线程
+        // 向调度器询问下一个线程，其他线程结束了的线程（例如进程已经退出）直接移除
         // 切换页表不会影响执行:
         // 因为在中断期间是操作系统正在执行，而操作系统所用到的内核线性映射是存在于每个页表中的。
-        if let Some(next_thread) = self.scheduler.get_next() {
+        let mut next = self.scheduler.get_next();
+        while let Some(thread) = next.as_ref().filter(|thread| thread.inner().dead) {
+            self.scheduler.remove_thread(thread);
+            next = self.scheduler.get_next();
+        }
+        if let Some(next_thread) = next {
             // 准备下一个线程
             let context = next_thread.prepare(); // 同时换入了新线程的页表。
             self.current_thread = Some(next_thread);
@@ -90,7 +106,20 @@ impl Processor {
         } else {
             // 没有活跃线程
             if self.sleeping_threads.is_empty() {
-                // 也没有休眠线程，则退出
+                // 也没有休眠线程，则退出。文件系统已经由写回线程在结束前写回，
+                // 调度器持有锁且关闭了中断，不在这里进行磁盘读写
+                // 此时只有空闲线程、缓存和内核的映射还占用物理页，可以据此检查是否有泄漏
+                let (used_frames, page_table_frames) = {
+                    let allocator = FRAME_ALLOCATOR.lock();
+                    (
+                        allocator.total_frames() - allocator.free_frames(),
+                        PAGE_TABLE_FRAMES.load(Ordering::Relaxed),
+                    )
+                };
+                println!(
+                    "{} frames still in use, {} of them as page tables",
+                    used_frames, page_table_frames
+                );
                 panic!("all threads terminated, shutting down");
             } else {
                 // 有休眠线程，则等待中断
@@ -139,10 +168,11 @@ impl Processor {
 
     /// fork 新线程
     /// fork 后应当为目前的线程复制一份几乎一样的拷贝，新线程与旧线程同属一个进程，公用页表和大部分内存空间，而新线程的栈是一份拷贝。
-    pub fn fork_current_thread(&mut self, context: &Context){
-        let thread = self.current_thread().fork(*context).unwrap();
+    pub fn fork_current_thread(&mut self, context: &Context) -> MemoryResult<()> {
+        let thread = self.current_thread().fork(*context)?;
         let priority = thread.inner().priority;
         println!("new thread {} forked from thread {}.", &thread.id, self.current_thread().id);
         self.scheduler.add_thread(thread, priority); // value moved here
+        Ok(())
     }
 }
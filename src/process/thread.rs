@@ -13,8 +13,6 @@ static mut THREAD_COUNTER: ThreadID = 0;
 pub struct Thread {
     /// 线程 ID
     pub id: ThreadID, // 用于唯一确认一个线程，它会在系统调用等时刻用到。
-    /// 线程的栈
-    pub stack: Range<VirtualAddress>, // 运行栈：每个线程都必须有一个独立的运行栈，保存运行时数据。这里只是记录栈的地址区间
     /// 所属的进程，使用 引用计数 增加安全性
     pub process: Arc<Process>, // 所属进程的记号：同一个进程中的多个线程，会共享页表、打开文件等信息。因此，我们将它们提取出来放到线程中。
     /// 用 `Mutex` 包装一些可变的变量
@@ -27,12 +25,18 @@ pub struct ThreadInner {
     /// 当线程不在执行时，我们需要保存其上下文（其实就是一堆寄存器的值），这样之后才能够将其恢复
     /// 当且仅当线程被暂停执行时，`context` 为 `Some`
     pub context: Option<Context>,
+    /// 线程的栈，运行栈：每个线程都必须有一个独立的运行栈，保存运行时数据。这里只是记录栈的地址区间
+    ///
+    /// 由用户自己提供栈的线程（例如 Linux 的 `clone`）为空区间，线程释放时不需要移除
+    pub stack: Range<VirtualAddress>,
     /// 是否进入休眠
     pub sleeping: bool,
     /// 是否已经结束
     pub dead: bool,
     /// priority, 用于Stride Scheduling 调度算法
     pub priority: usize,
+    /// Linux 的 `set_tid_address`：线程结束时将此用户地址处的整数清零，为 0 时不做处理
+    pub clear_child_tid: usize,
 }
 
 // 单个线程级的操作
@@ -68,27 +72,56 @@ impl Thread {
     ) -> MemoryResult<Arc<Thread>> {
         // 让 所属进程 分配一段连续虚拟空间并映射一段物理空间，作为线程的栈
         // 也就是，线程时资源的使用者，该资源从进程那里获取，进程并不会使用这些资源，而只是向操作系统索取。
-        // 页面段的权限包括: Flags::READABLE(R), Flags::WRITABLE(W). 以及 process 是否是用户态进程(U)
-        let stack = process.alloc_page_range(STACK_SIZE, Flags::READABLE | Flags::WRITABLE)?;
+        // 页面段的权限包括: Flags::READABLE(R), Flags::WRITABLE(W)，栈可执行时还有 Flags::EXECUTABLE(X). 以及 process 是否是用户态进程(U)
+        let stack_flags = process.inner().stack_flags;
+        let stack = process.alloc_page_range(STACK_SIZE, stack_flags)?;
 
         // 构建线程的 Context, 包括 sepc 设置为entry_point，sp设为stack.end.into()(即线程栈顶), 压入参数arguments(<8个), sstatus的spp位 = is_user 
         let context = Context::new(stack.end.into(), entry_point, arguments, process.is_user);
 
         // 打包成线程
+        Ok(Self::from_context(process, stack, context, priority))
+    }
+
+    /// 用给定的栈和 `Context` 创建线程，并加入所属进程
+    ///
+    /// `stack` 必须已经在进程中映射，线程释放时会将其移除；为空区间时表示栈由用户管理
+    pub fn from_context(
+        process: Arc<Process>,
+        stack: Range<VirtualAddress>,
+        context: Context,
+        priority: usize,
+    ) -> Arc<Thread> {
         let thread = Arc::new(Thread {
             id: unsafe {
                 THREAD_COUNTER += 1;
                 THREAD_COUNTER
             },
-            stack,   // 线程栈
             process, // 所属进程
             inner: Mutex::new(ThreadInner {
                 context: Some(context), // 上下文
+                stack,           // 线程栈
                 sleeping: false, // 非休眠
                 dead: false,     // 非kill
-                priority: priority,
+                priority,
+                clear_child_tid: 0,
             }),
         });
+        thread.process.add_thread(&thread);
+        thread
+    }
+
+    /// 创建用户进程的第一个线程，在栈上放置参数、环境变量和辅助向量
+    pub fn new_user(
+        process: Arc<Process>,
+        entry_point: usize,
+        init_info: &InitInfo,
+        priority: usize,
+    ) -> MemoryResult<Arc<Thread>> {
+        let thread = Self::new(process, entry_point, None, priority)?;
+        let stack_top = thread.inner().stack.end;
+        let sp = init_info.push_at(&thread.process.inner().memory_set, stack_top, STACK_SIZE)?;
+        thread.inner().context.as_mut().unwrap().set_sp(sp.into());
         Ok(thread)
     }
 
@@ -101,31 +134,39 @@ impl Thread {
     /// fork 后应当为目前的线程复制一份几乎一样的拷贝，新线程与旧线程同属一个进程，公用页表和大部分内存空间，而新线程的栈是一份拷贝。
     pub fn fork(&self, current_context: Context) -> MemoryResult<Arc<Thread>> {
         // 让所属进程分配并映射一段空间，作为线程的栈
-        let stack = self.process.alloc_page_range(STACK_SIZE, Flags::READABLE | Flags::WRITABLE)?;
-        // 新线程的栈是原先线程栈的拷贝 (原样复制)
+        let stack_flags = self.process.inner().stack_flags;
+        let stack = self.process.alloc_page_range(STACK_SIZE, stack_flags)?;
+        let (current_stack, priority) = {
+            let inner = self.inner();
+            (inner.stack, inner.priority)
+        };
+        // 新线程的栈是原先线程栈的拷贝 (原样复制)，用户线程的栈需要打开 SUM 才能访问
+        let _sum = SumGuard::new();
         for i in 0..STACK_SIZE {
-            *VirtualAddress(stack.start.0 + i).deref::<u8>() = *VirtualAddress(self.stack.start.0 + i).deref::<u8>()
+            *VirtualAddress(stack.start.0 + i).deref::<u8>() = *VirtualAddress(current_stack.start.0 + i).deref::<u8>()
         }
         // 构建线程的 Context, 包括 sepc 设置为entry_point，sp设为stack.end.into()(即线程栈顶), 压入参数arguments(<8个), sstatus的spp位 = is_user 
         let mut context = current_context.clone();
         // sp 指向新线程的上下文
-        context.set_sp( usize::from(stack.start) -  usize::from(self.stack.start) + current_context.sp() );
+        context.set_sp( usize::from(stack.start) -  usize::from(current_stack.start) + current_context.sp() );
         // 打包成线程
-        let thread = Arc::new(Thread {
-            id: unsafe {
-                THREAD_COUNTER += 1;
-                THREAD_COUNTER
-            },
-            stack,   // 线程栈
-            process: Arc::clone(&self.process), // 所属进程
-            inner: Mutex::new(ThreadInner {
-                context: Some(context), // 上下文
-                sleeping: false, // 非休眠
-                dead: false,     // 非kill
-                priority: self.inner().priority.clone(),
-            }),
-        });
-        Ok(thread)
+        Ok(Self::from_context(Arc::clone(&self.process), stack, context, priority))
+    }
+}
+
+/// 线程释放时从所属进程的 [`MemorySet`] 中移除它的栈，释放相应的物理页
+impl Drop for Thread {
+    fn drop(&mut self) {
+        let stack = self.inner.get_mut().stack;
+        if stack.start == stack.end {
+            return;
+        }
+        let range = Range::from(
+            VirtualPageNumber::floor(stack.start)..VirtualPageNumber::ceil(stack.end),
+        );
+        if self.process.inner().memory_set.remove_range(range).is_err() {
+            println!("failed to remove stack of thread {}", self.id);
+        }
     }
 }
 
@@ -156,7 +197,7 @@ impl core::fmt::Debug for Thread {
         formatter
             .debug_struct("Thread")
             .field("thread_id", &self.id)
-            .field("stack", &self.stack)
+            .field("stack", &self.inner().stack)
             .field("context", &self.inner().context)
             .finish()
     }
@@ -0,0 +1,255 @@
+//! 生成和读取内核挂载的 SFS 磁盘镜像
+//!
+//! ```text
+//! mksfs create <镜像> <大小> <来源>[:<目录>]...
+//! mksfs list <镜像> [<路径>]
+//! mksfs extract <镜像> <输出目录>
+//! ```
+//!
+//! - `create`：创建指定大小的镜像，大小可以带 `K`、`M`、`G` 后缀。
+//!   来源为目录时复制其中的全部内容，为文件时复制文件本身，放在镜像中的 `<目录>` 下，默认为根目录
+//! - `list`：列出镜像中的文件和大小
+//! - `extract`：将镜像中的全部文件复制到主机的目录中
+
+use rcore_fs::vfs::{FileSystem, FileType, FsError, INode};
+use rcore_fs_sfs::SimpleFileSystem;
+use std::{
+    env, fmt,
+    fs::{self, OpenOptions},
+    io,
+    path::Path,
+    process,
+    sync::{Arc, Mutex},
+};
+
+const USAGE: &str = "usage:
+    mksfs create <image> <size> <source>[:<dir>]...
+    mksfs list <image> [<path>]
+    mksfs extract <image> <output dir>";
+
+/// 工具中出现的错误
+#[derive(Debug)]
+enum Error {
+    /// 读写主机上的文件出错
+    Io(io::Error),
+    /// 读写镜像中的文件系统出错
+    Fs(FsError),
+    /// 参数不合法
+    Usage(String),
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+impl From<FsError> for Error {
+    fn from(error: FsError) -> Self {
+        Error::Fs(error)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(error) => write!(f, "{}", error),
+            Error::Fs(error) => write!(f, "filesystem error: {:?}", error),
+            Error::Usage(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let result = match args.as_slice() {
+        ["create", image, size, sources @ ..] => {
+            parse_size(size).and_then(|size| create(image, size, sources))
+        }
+        ["list", image] => list(image, "/"),
+        ["list", image, path] => list(image, path),
+        ["extract", image, output] => extract(image, output),
+        _ => Err(Error::Usage(USAGE.into())),
+    };
+    if let Err(error) = result {
+        eprintln!("mksfs: {}", error);
+        process::exit(1);
+    }
+}
+
+/// 解析镜像大小，可以带 `K`、`M`、`G` 后缀
+fn parse_size(size: &str) -> Result<usize> {
+    let (number, unit) = match size.to_ascii_uppercase().chars().last() {
+        Some('K') => (&size[..size.len() - 1], 1 << 10),
+        Some('M') => (&size[..size.len() - 1], 1 << 20),
+        Some('G') => (&size[..size.len() - 1], 1 << 30),
+        _ => (size, 1),
+    };
+    number
+        .parse::<usize>()
+        .ok()
+        .and_then(|number| number.checked_mul(unit))
+        .ok_or_else(|| Error::Usage(format!("invalid image size: {}", size)))
+}
+
+/// 打开已有的镜像
+fn open_image(image: &str) -> Result<Arc<SimpleFileSystem>> {
+    let file = OpenOptions::new().read(true).write(true).open(image)?;
+    Ok(SimpleFileSystem::open(Arc::new(Mutex::new(file)))?)
+}
+
+/// 创建镜像并复制所有来源
+fn create(image: &str, size: usize, sources: &[&str]) -> Result<()> {
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(image)?;
+    file.set_len(size as u64)?;
+    let sfs = SimpleFileSystem::create(Arc::new(Mutex::new(file)), size)?;
+    let root = sfs.root_inode();
+    for source in sources {
+        let (source, target) = match source.find(':') {
+            Some(index) => (&source[..index], &source[index + 1..]),
+            None => (*source, "/"),
+        };
+        let dir = create_dirs(&root, target)?;
+        let path = Path::new(source);
+        if path.is_dir() {
+            copy_dir_in(path, &dir)?;
+        } else {
+            copy_file_in(path, &dir)?;
+        }
+    }
+    sfs.sync()?;
+    Ok(())
+}
+
+/// 找到镜像中的目录，沿途不存在的目录依次创建
+fn create_dirs(root: &Arc<dyn INode>, path: &str) -> Result<Arc<dyn INode>> {
+    let mut dir = root.clone();
+    for name in path.split('/').filter(|name| !name.is_empty()) {
+        dir = match dir.find(name) {
+            Ok(inode) => inode,
+            Err(FsError::EntryNotFound) => dir.create(name, FileType::Dir, 0o755)?,
+            Err(error) => return Err(error.into()),
+        };
+    }
+    Ok(dir)
+}
+
+/// 将主机目录中的全部内容复制到镜像的目录 `dir` 中
+fn copy_dir_in(path: &Path, dir: &Arc<dyn INode>) -> Result<()> {
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            copy_dir_in(&path, &create_dirs(dir, &name)?)?;
+        } else {
+            copy_file_in(&path, dir)?;
+        }
+    }
+    Ok(())
+}
+
+/// 将主机上的文件复制到镜像的目录 `dir` 中，同名的文件被覆盖
+fn copy_file_in(path: &Path, dir: &Arc<dyn INode>) -> Result<()> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| Error::Usage(format!("invalid source: {}", path.display())))?
+        .to_string_lossy()
+        .into_owned();
+    let data = fs::read(path)?;
+    let inode = match dir.find(&name) {
+        Ok(inode) => {
+            inode.resize(0)?;
+            inode
+        }
+        Err(FsError::EntryNotFound) => dir.create(&name, FileType::File, 0o755)?,
+        Err(error) => return Err(error.into()),
+    };
+    write_all(&inode, &data)
+}
+
+/// 从头写入文件的全部内容
+fn write_all(inode: &Arc<dyn INode>, data: &[u8]) -> Result<()> {
+    let mut offset = 0;
+    while offset < data.len() {
+        let written = inode.write_at(offset, &data[offset..])?;
+        if written == 0 {
+            return Err(FsError::NoDeviceSpace.into());
+        }
+        offset += written;
+    }
+    Ok(())
+}
+
+/// 读出文件的全部内容
+fn read_all(inode: &Arc<dyn INode>) -> Result<Vec<u8>> {
+    let mut data = vec![0; inode.metadata()?.size];
+    let mut offset = 0;
+    while offset < data.len() {
+        let read = inode.read_at(offset, &mut data[offset..])?;
+        if read == 0 {
+            break;
+        }
+        offset += read;
+    }
+    data.truncate(offset);
+    Ok(data)
+}
+
+/// 目录中除 `.` 和 `..` 之外的文件名
+fn entries(dir: &Arc<dyn INode>) -> Result<Vec<String>> {
+    Ok(dir
+        .list()?
+        .into_iter()
+        .filter(|name| name != "." && name != "..")
+        .collect())
+}
+
+/// 列出镜像中的文件，目录递归列出
+fn list(image: &str, path: &str) -> Result<()> {
+    let sfs = open_image(image)?;
+    let inode = sfs.root_inode().lookup(path)?;
+    list_inode(&inode, path.trim_end_matches('/'))
+}
+
+fn list_inode(inode: &Arc<dyn INode>, path: &str) -> Result<()> {
+    let metadata = inode.metadata()?;
+    if metadata.type_ != FileType::Dir {
+        println!("{:>10}  {}", metadata.size, path);
+        return Ok(());
+    }
+    println!("{:>10}  {}/", "-", path);
+    for name in entries(inode)? {
+        list_inode(&inode.find(&name)?, &format!("{}/{}", path, name))?;
+    }
+    Ok(())
+}
+
+/// 将镜像中的全部文件复制到主机的目录中
+fn extract(image: &str, output: &str) -> Result<()> {
+    let sfs = open_image(image)?;
+    extract_dir(&sfs.root_inode(), Path::new(output))
+}
+
+fn extract_dir(dir: &Arc<dyn INode>, path: &Path) -> Result<()> {
+    fs::create_dir_all(path)?;
+    for name in entries(dir)? {
+        let inode = dir.find(&name)?;
+        let target = path.join(&name);
+        match inode.metadata()?.type_ {
+            FileType::Dir => extract_dir(&inode, &target)?,
+            FileType::File => fs::write(&target, read_all(&inode)?)?,
+            // 设备和符号链接等其他类型的文件不导出
+            _ => println!("skipping {}", target.display()),
+        }
+    }
+    Ok(())
+}
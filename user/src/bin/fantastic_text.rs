@@ -0,0 +1,18 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+/// 用终端的控制字符输出各种颜色和样式的文字
+#[no_mangle]
+pub fn main() -> usize {
+    println!("\x1b[31mred\x1b[0m \x1b[32mgreen\x1b[0m \x1b[33myellow\x1b[0m");
+    println!("\x1b[34mblue\x1b[0m \x1b[35mmagenta\x1b[0m \x1b[36mcyan\x1b[0m");
+    println!("\x1b[1mbold\x1b[0m \x1b[4munderline\x1b[0m \x1b[7mreverse\x1b[0m");
+    for i in 0..8 {
+        print!("\x1b[4{}m  \x1b[0m", i);
+    }
+    println!("");
+    0
+}
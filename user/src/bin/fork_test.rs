@@ -0,0 +1,20 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::*;
+
+/// 连续 fork 三次，得到八个执行流，各自独立修改栈上的变量
+#[no_mangle]
+pub fn main() -> usize {
+    let mut depth = 0;
+    for _ in 0..3 {
+        if sys_fork() == 0 {
+            depth += 1;
+        }
+    }
+    println!("thread {} at depth {}", sys_gettid(), depth);
+    0
+}
@@ -0,0 +1,36 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::*;
+
+/// 测试 open、read、write、fsync、sync、mount 和 umount
+#[no_mangle]
+pub fn main() -> usize {
+    // 读取 procfs 中的文件
+    let fd = sys_open("/proc/meminfo");
+    assert!(fd >= 0, "open /proc/meminfo: {}", fd);
+    let mut buffer = [0u8; 512];
+    let count = sys_read(fd as usize, &mut buffer);
+    assert!(count >= 0, "read /proc/meminfo: {}", count);
+    println!("/proc/meminfo:");
+    sys_write(STDOUT, &buffer[..count as usize]);
+
+    // 写入设备文件
+    let fd = sys_open("/dev/null");
+    assert!(fd >= 0, "open /dev/null: {}", fd);
+    assert_eq!(sys_write(fd as usize, b"discarded"), 9);
+    println!("fsync /dev/null: {}", sys_fsync(fd as usize));
+
+    // 重新挂载 /tmp，打开不存在的文件应当失败
+    assert_eq!(sys_umount("/tmp"), 0);
+    assert_eq!(sys_mount("tmpfs", "/tmp", "tmpfs"), 0);
+    assert!(sys_open("/tmp/missing") < 0);
+    assert!(sys_umount("/no/such/mount") < 0);
+
+    assert_eq!(sys_sync(), 0);
+    println!("fs_test passed");
+    0
+}
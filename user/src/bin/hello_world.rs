@@ -0,0 +1,18 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{env, sys_gettid};
+
+/// 打印问候、线程 ID 和程序的参数
+#[no_mangle]
+pub fn main() -> usize {
+    println!("Hello world from user mode program!");
+    println!("my thread id is {}", sys_gettid());
+    for (i, arg) in env::args().enumerate() {
+        println!("argv[{}] = {}", i, arg);
+    }
+    0
+}
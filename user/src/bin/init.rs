@@ -0,0 +1,168 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::{string::String, vec::Vec};
+use user_lib::*;
+
+/// 配置文件的路径
+const INITTAB: &str = "/etc/inittab";
+/// 子进程执行程序失败时的退出码，这样的程序不会重新启动
+const EXEC_FAILED: i32 = 127;
+
+/// 配置文件中一行的动作
+#[derive(Clone, Copy, PartialEq)]
+enum Action {
+    /// 启动后不再管理
+    Once,
+    /// 启动并等待它结束
+    Wait,
+    /// 结束后重新启动
+    Respawn,
+}
+
+/// 配置文件中的一行
+struct Entry {
+    action: Action,
+    command: String,
+    /// 正在运行的进程 ID
+    pid: Option<isize>,
+}
+
+/// 读出文件的全部内容
+fn read_file(path: &str) -> Option<String> {
+    let fd = sys_open(path);
+    if fd < 0 {
+        return None;
+    }
+    let mut content = Vec::new();
+    let mut buffer = [0u8; 512];
+    loop {
+        let count = sys_read(fd as usize, &mut buffer);
+        if count <= 0 {
+            break;
+        }
+        content.extend_from_slice(&buffer[..count as usize]);
+    }
+    String::from_utf8(content).ok()
+}
+
+/// 解析配置文件，忽略空行、注释和不能识别的行
+fn parse(inittab: &str) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    for (number, line) in inittab.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (action, command) = match line.find(':') {
+            Some(index) => (&line[..index], line[index + 1..].trim()),
+            None => ("", ""),
+        };
+        let action = match action {
+            "once" => Action::Once,
+            "wait" => Action::Wait,
+            "respawn" => Action::Respawn,
+            _ => {
+                println!("init: {}:{}: invalid entry", INITTAB, number + 1);
+                continue;
+            }
+        };
+        if command.is_empty() {
+            println!("init: {}:{}: missing command", INITTAB, number + 1);
+            continue;
+        }
+        entries.push(Entry {
+            action,
+            command: command.into(),
+            pid: None,
+        });
+    }
+    entries
+}
+
+/// 在子进程中执行命令，返回子进程的 ID
+fn spawn(command: &str) -> Option<isize> {
+    let args: Vec<&str> = command.split_whitespace().collect();
+    let pid = sys_clone(0, 0);
+    if pid == 0 {
+        let error = sys_execve(args[0], &args, &[]);
+        println!("init: failed to execute {}: error {}", args[0], error);
+        sys_exit(EXEC_FAILED as isize);
+    }
+    if pid < 0 {
+        println!("init: failed to start {}: error {}", command, pid);
+        return None;
+    }
+    Some(pid)
+}
+
+/// 等待任意子进程结束，返回其进程 ID 和退出码
+///
+/// 暂时没有子进程时让出处理机，等待其他进程结束后交给 init 的子进程
+fn wait_any() -> (isize, i32) {
+    let mut status = 0;
+    loop {
+        let pid = sys_wait4(-1, &mut status, 0);
+        if pid > 0 {
+            return (pid, (status >> 8) & 0xff);
+        }
+        sys_yield();
+    }
+}
+
+/// 处理一个结束的子进程，需要时重新启动
+fn reap(entries: &mut [Entry], pid: isize, code: i32) {
+    match entries.iter_mut().find(|entry| entry.pid == Some(pid)) {
+        Some(entry) => {
+            println!(
+                "init: {} (pid {}) exited with code {}",
+                entry.command, pid, code
+            );
+            entry.pid = None;
+            if entry.action == Action::Respawn && code != EXEC_FAILED {
+                entry.pid = spawn(&entry.command);
+            }
+        }
+        None => println!("init: reaped orphan process {}", pid),
+    }
+}
+
+/// 按配置文件启动程序，然后回收子进程和交给 init 的孤儿进程
+#[no_mangle]
+pub fn main() -> usize {
+    let inittab = match read_file(INITTAB) {
+        Some(inittab) => inittab,
+        None => {
+            println!("init: cannot read {}", INITTAB);
+            String::new()
+        }
+    };
+    let mut entries = parse(&inittab);
+    for i in 0..entries.len() {
+        entries[i].pid = spawn(&entries[i].command);
+        if entries[i].action != Action::Wait {
+            continue;
+        }
+        // 等待这一行的程序结束，期间结束的其他进程照常处理
+        while let Some(pid) = entries[i].pid {
+            let (exited, code) = wait_any();
+            if exited == pid {
+                println!(
+                    "init: {} (pid {}) exited with code {}",
+                    entries[i].command, pid, code
+                );
+                entries[i].pid = None;
+            } else {
+                reap(&mut entries, exited, code);
+            }
+        }
+    }
+    loop {
+        let (pid, code) = wait_any();
+        reap(&mut entries, pid, code);
+    }
+}
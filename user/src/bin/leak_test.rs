@@ -0,0 +1,111 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::*;
+
+/// 预热之后测试的轮数
+const ROUNDS: usize = 4;
+/// 每轮创建的子进程数
+const PROCESSES: usize = 4;
+/// 每个子进程中再复制出的线程数
+const THREADS: usize = 4;
+/// 每个线程映射的匿名内存大小，足以用到 2 MiB 的大页，并建立新的中间页表
+const MAP_SIZE: usize = 4 << 20;
+
+/// 读取 `/proc/meminfo` 中以 kB 为单位的一项
+fn field(text: &str, name: &str) -> usize {
+    text.lines()
+        .find_map(|line| line.strip_prefix(name))
+        .and_then(|value| value.trim().trim_end_matches("kB").trim().parse().ok())
+        .expect(name)
+}
+
+/// 除页缓存以外已经使用的物理内存，以及页表占用的内存，单位为 kB
+fn usage() -> (usize, usize) {
+    let fd = sys_open("/proc/meminfo");
+    assert!(fd >= 0, "open: {}", fd);
+    let mut buffer = [0u8; 512];
+    let mut length = 0;
+    loop {
+        let read = sys_read(fd as usize, &mut buffer[length..]);
+        assert!(read >= 0, "read: {}", read);
+        if read == 0 {
+            break;
+        }
+        length += read as usize;
+    }
+    let text = core::str::from_utf8(&buffer[..length]).unwrap();
+    let used = field(text, "MemTotal:") - field(text, "MemFree:") - field(text, "Cached:");
+    (used, field(text, "PageTables:"))
+}
+
+/// 映射一段匿名内存，逐页写入后解除映射
+fn touch_memory() {
+    let address = sys_mmap(
+        0,
+        MAP_SIZE,
+        PROT_READ | PROT_WRITE,
+        MAP_PRIVATE | MAP_ANONYMOUS,
+        usize::MAX,
+        0,
+    );
+    assert!(address > 0, "mmap: {}", address);
+    for offset in (0..MAP_SIZE).step_by(PAGE_SIZE) {
+        unsafe { *((address as usize + offset) as *mut usize) = offset };
+    }
+    assert_eq!(sys_munmap(address as usize, MAP_SIZE), 0);
+}
+
+/// 创建若干子进程，每个子进程中的线程各自映射内存后结束，然后回收所有子进程
+fn round() {
+    for _ in 0..PROCESSES {
+        let pid = sys_clone(0, 0);
+        if pid == 0 {
+            // 新线程得到 0，不再继续复制
+            for _ in 0..THREADS {
+                if sys_fork() == 0 {
+                    break;
+                }
+            }
+            touch_memory();
+            // 最后一个线程结束时子进程结束
+            sys_exit(0);
+        }
+        assert!(pid > 0, "clone: {}", pid);
+    }
+    for _ in 0..PROCESSES {
+        let mut status = 0;
+        let pid = sys_wait4(-1, &mut status, 0);
+        assert!(pid > 0, "wait4: {}", pid);
+    }
+}
+
+/// 检查线程的栈、进程的地址空间和页表在线程结束、进程被回收后全部释放
+#[no_mangle]
+pub fn main() -> usize {
+    // 先运行一轮，让只分配一次的资源（例如本进程的堆）稳定下来
+    round();
+    let before = usage();
+    for _ in 0..ROUNDS {
+        round();
+    }
+    // 结束的线程在下一次调度时才被释放，让出处理机等待它们释放
+    let mut after = usage();
+    for _ in 0..100 {
+        if after == before {
+            break;
+        }
+        sys_yield();
+        after = usage();
+    }
+    println!(
+        "used {} kB -> {} kB, page tables {} kB -> {} kB",
+        before.0, after.0, before.1, after.1
+    );
+    assert_eq!(after, before, "frames leaked");
+    println!("leak_test passed");
+    0
+}
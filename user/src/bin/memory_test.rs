@@ -0,0 +1,53 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::{collections::BTreeMap, vec, vec::Vec};
+use user_lib::*;
+
+/// 测试堆分配、brk、mmap、mprotect 和 munmap
+#[no_mangle]
+pub fn main() -> usize {
+    // 小的分配在 brk 扩展的堆中
+    let brk = sys_brk(0);
+    let mut map = BTreeMap::new();
+    for i in 0..1000usize {
+        map.insert(i, i * i);
+    }
+    assert_eq!(map[&999], 998001);
+    assert!(sys_brk(0) > brk, "heap did not grow");
+
+    // 大的分配直接使用 mmap
+    let mut large: Vec<u8> = vec![0; 1 << 20];
+    for (i, byte) in large.iter_mut().enumerate() {
+        *byte = i as u8;
+    }
+    assert_eq!(large[12345], 12345usize as u8);
+    drop(large);
+
+    // 直接映射匿名内存
+    let length = 4 * PAGE_SIZE;
+    let address = sys_mmap(
+        0,
+        length,
+        PROT_READ | PROT_WRITE,
+        MAP_PRIVATE | MAP_ANONYMOUS,
+        usize::MAX,
+        0,
+    );
+    assert!(address > 0, "mmap: {}", address);
+    let pages = unsafe { core::slice::from_raw_parts_mut(address as *mut usize, length / 8) };
+    pages[0] = 42;
+    pages[pages.len() - 1] = 43;
+    assert_eq!(sys_mprotect(address as usize, PAGE_SIZE, PROT_READ), 0);
+    assert_eq!(pages[0], 42);
+    assert_eq!(sys_munmap(address as usize, length), 0);
+    // 映射已经解除，修改权限应当失败
+    assert!(sys_mprotect(address as usize, PAGE_SIZE, PROT_READ) < 0);
+
+    println!("memory_test passed");
+    0
+}
@@ -0,0 +1,34 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::ptr::{read_volatile, write_volatile};
+use user_lib::*;
+
+/// 父子进程通过共享内存传递一个数
+#[no_mangle]
+pub fn main() -> usize {
+    let id = sys_shm_create(PAGE_SIZE);
+    assert!(id >= 0, "shm_create: {}", id);
+    let address = sys_shm_attach(id as usize, 0, PROT_READ | PROT_WRITE);
+    assert!(address > 0, "shm_attach: {}", address);
+    let shared = address as *mut usize;
+    unsafe { write_volatile(shared, 0) };
+
+    let pid = sys_clone(0, 0);
+    if pid == 0 {
+        // 子进程有自己的地址空间，只有共享内存中的修改对父进程可见
+        unsafe { write_volatile(shared, 0xdead_beef) };
+        println!("child {} wrote to shared memory", sys_getpid());
+        return 0;
+    }
+    assert!(pid > 0, "clone: {}", pid);
+    let mut status = 0;
+    assert_eq!(sys_wait4(pid, &mut status, 0), pid);
+    assert_eq!(unsafe { read_volatile(shared) }, 0xdead_beef);
+    assert_eq!(sys_shm_detach(address as usize), 0);
+    println!("shm_test passed");
+    0
+}
@@ -0,0 +1,88 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::{string::String, vec::Vec};
+use user_lib::*;
+
+const LF: u8 = b'\n';
+const CR: u8 = b'\r';
+const BS: u8 = 0x08;
+const DEL: u8 = 0x7f;
+
+/// 打开文件并输出全部内容
+fn cat(path: &str) {
+    let fd = sys_open(path);
+    if fd < 0 {
+        println!("cat: {}: error {}", path, fd);
+        return;
+    }
+    let mut buffer = [0u8; 256];
+    loop {
+        let count = sys_read(fd as usize, &mut buffer);
+        if count <= 0 {
+            break;
+        }
+        sys_write(STDOUT, &buffer[..count as usize]);
+    }
+}
+
+/// 执行一行命令，返回 `false` 时退出
+fn run(line: &str) -> bool {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let ret = match words.as_slice() {
+        [] => 0,
+        ["exit"] => return false,
+        ["help"] => {
+            println!("commands: help, cat <path>, mount <source> <target> <fstype>, umount <target>, sync, exit");
+            0
+        }
+        ["cat", path] => {
+            cat(path);
+            0
+        }
+        ["mount", source, target, fstype] => sys_mount(source, target, fstype),
+        ["umount", target] => sys_umount(target),
+        ["sync"] => sys_sync(),
+        _ => {
+            println!("unknown command: {}", line);
+            0
+        }
+    };
+    if ret < 0 {
+        println!("{}: error {}", words[0], ret);
+    }
+    true
+}
+
+/// 逐行读取并执行内置命令
+#[no_mangle]
+pub fn main() -> usize {
+    let mut line = String::new();
+    print!(">> ");
+    loop {
+        let c = getchar();
+        match c {
+            LF | CR => {
+                println!("");
+                if !run(&line) {
+                    return 0;
+                }
+                line.clear();
+                print!(">> ");
+            }
+            BS | DEL => {
+                if line.pop().is_some() {
+                    print!("\x08 \x08");
+                }
+            }
+            _ => {
+                print!("{}", c as char);
+                line.push(c as char);
+            }
+        }
+    }
+}
@@ -0,0 +1,54 @@
+//! 通过标准输入输出的文件描述符实现格式化输出和读取字符
+
+use crate::syscall::{sys_read, sys_write};
+use core::fmt::{self, Write};
+
+/// 标准输入的文件描述符
+pub const STDIN: usize = 0;
+/// 标准输出的文件描述符
+pub const STDOUT: usize = 1;
+
+/// 一个 ZST，实现 [`core::fmt::Write`] trait 来进行格式化输出
+struct Stdout;
+
+impl Write for Stdout {
+    /// 整个字符串由一次系统调用写出
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if sys_write(STDOUT, s.as_bytes()) < 0 {
+            Err(fmt::Error)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// 打印由 [`core::format_args!`] 格式化后的数据
+///
+/// [`print!`] 和 [`println!`] 宏都将展开成此函数。写出失败时忽略，避免 panic 时再次 panic
+pub fn print(args: fmt::Arguments) {
+    let _ = Stdout.write_fmt(args);
+}
+
+/// 实现类似于标准库中的 `print!` 宏
+#[macro_export]
+macro_rules! print {
+    ($fmt: literal $(, $($arg: tt)+)?) => {
+        $crate::console::print(format_args!($fmt $(, $($arg)+)?));
+    }
+}
+
+/// 实现类似于标准库中的 `println!` 宏
+#[macro_export]
+macro_rules! println {
+    ($fmt: literal $(, $($arg: tt)+)?) => {
+        $crate::console::print(format_args!(concat!($fmt, "\n") $(, $($arg)+)?));
+    }
+}
+
+/// 从标准输入读取一个字符，没有输入时等待
+pub fn getchar() -> u8 {
+    let mut c = [0u8; 1];
+    // 暂无数据时读到 0 个字节，被唤醒后重新读取
+    while sys_read(STDIN, &mut c) <= 0 {}
+    c[0]
+}
@@ -0,0 +1,60 @@
+//! 程序的参数和环境变量
+//!
+//! 字符串都在内核构造的初始栈上，不需要复制，在程序运行期间一直有效
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// 参数的个数
+static ARGC: AtomicUsize = AtomicUsize::new(0);
+/// 参数指针数组的地址
+static ARGV: AtomicUsize = AtomicUsize::new(0);
+/// 环境变量指针数组的地址，以空指针结尾
+static ENVP: AtomicUsize = AtomicUsize::new(0);
+
+/// 从初始栈顶读取 argc、argv 和 envp 的位置，由 `_start` 调用
+pub(crate) unsafe fn init(sp: *const usize) {
+    let argc = *sp;
+    let argv = sp.add(1);
+    ARGC.store(argc, Ordering::Relaxed);
+    ARGV.store(argv as usize, Ordering::Relaxed);
+    // envp 紧跟在 argv 的空指针之后
+    ENVP.store(argv.add(argc + 1) as usize, Ordering::Relaxed);
+}
+
+/// 读取以 `\0` 结尾的字符串，不是合法的 UTF-8 时返回空串
+unsafe fn c_str(pointer: *const u8) -> &'static str {
+    let mut length = 0;
+    while *pointer.add(length) != 0 {
+        length += 1;
+    }
+    core::str::from_utf8(core::slice::from_raw_parts(pointer, length)).unwrap_or("")
+}
+
+/// 程序的参数，第一个通常是程序名
+pub fn args() -> impl Iterator<Item = &'static str> {
+    let argv = ARGV.load(Ordering::Relaxed) as *const *const u8;
+    (0..ARGC.load(Ordering::Relaxed)).map(move |i| unsafe { c_str(*argv.add(i)) })
+}
+
+/// 所有环境变量，每项为名称和值
+pub fn vars() -> impl Iterator<Item = (&'static str, &'static str)> {
+    let mut envp = ENVP.load(Ordering::Relaxed) as *const *const u8;
+    core::iter::from_fn(move || unsafe {
+        if envp.is_null() || (*envp).is_null() {
+            return None;
+        }
+        let entry = c_str(*envp);
+        envp = envp.add(1);
+        Some(match entry.find('=') {
+            Some(index) => (&entry[..index], &entry[index + 1..]),
+            None => (entry, ""),
+        })
+    })
+}
+
+/// 名为 `key` 的环境变量的值
+pub fn var(key: &str) -> Option<&'static str> {
+    vars()
+        .find(|(name, _)| *name == key)
+        .map(|(_, value)| value)
+}
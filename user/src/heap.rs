@@ -0,0 +1,102 @@
+//! 用户程序的堆
+//!
+//! 较小的分配由 `buddy_system_allocator` 在程序断点之后的堆中进行，空间不足时用 `brk` 扩展；
+//! 较大的分配直接使用匿名的 `mmap`，释放时 `munmap` 归还给内核。
+
+use crate::syscall::*;
+use alloc::alloc::{GlobalAlloc, Layout};
+use buddy_system_allocator::Heap;
+use core::ptr::{null_mut, NonNull};
+use spin::Mutex;
+
+/// 每次扩展堆的最小字节数
+const HEAP_GROW_SIZE: usize = 16 * PAGE_SIZE;
+/// 不小于这个大小的分配直接使用 `mmap`
+const MMAP_THRESHOLD: usize = 32 * PAGE_SIZE;
+
+/// 按页向上取整
+fn page_round_up(size: usize) -> usize {
+    (size + PAGE_SIZE - 1) & !(PAGE_SIZE - 1)
+}
+
+/// 由 `brk` 扩展的堆
+struct BrkHeap {
+    heap: Heap,
+    /// 当前的程序断点，为 0 时还没有向内核查询
+    brk: usize,
+}
+
+impl BrkHeap {
+    /// 将程序断点后移至少 `size` 字节，并将新的空间加入堆中
+    fn grow(&mut self, size: usize) -> bool {
+        if self.brk == 0 {
+            self.brk = sys_brk(0) as usize;
+        }
+        let new_brk = self.brk + page_round_up(size.max(HEAP_GROW_SIZE));
+        // 内核总是返回调整后的断点，没有变化说明扩展失败
+        if sys_brk(new_brk) as usize != new_brk {
+            return false;
+        }
+        unsafe { self.heap.add_to_heap(self.brk, new_brk) };
+        self.brk = new_brk;
+        true
+    }
+}
+
+/// 用户程序的全局分配器
+struct UserAllocator(Mutex<BrkHeap>);
+
+unsafe impl GlobalAlloc for UserAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        // mmap 返回的地址按页对齐，更大的对齐要求仍在堆中分配
+        if layout.size() >= MMAP_THRESHOLD && layout.align() <= PAGE_SIZE {
+            let address = sys_mmap(
+                0,
+                page_round_up(layout.size()),
+                PROT_READ | PROT_WRITE,
+                MAP_PRIVATE | MAP_ANONYMOUS,
+                usize::MAX,
+                0,
+            );
+            return if address < 0 {
+                null_mut()
+            } else {
+                address as *mut u8
+            };
+        }
+        let mut inner = self.0.lock();
+        loop {
+            if let Ok(pointer) = inner.heap.alloc(layout) {
+                return pointer.as_ptr();
+            }
+            // 伙伴系统按 2 的幂分配，扩展时留出对齐所需的空间
+            let needed = layout.size().max(layout.align()).next_power_of_two() * 2;
+            if !inner.grow(needed) {
+                return null_mut();
+            }
+        }
+    }
+
+    unsafe fn dealloc(&self, pointer: *mut u8, layout: Layout) {
+        if layout.size() >= MMAP_THRESHOLD && layout.align() <= PAGE_SIZE {
+            sys_munmap(pointer as usize, page_round_up(layout.size()));
+        } else {
+            self.0
+                .lock()
+                .heap
+                .dealloc(NonNull::new_unchecked(pointer), layout);
+        }
+    }
+}
+
+#[global_allocator]
+static HEAP: UserAllocator = UserAllocator(Mutex::new(BrkHeap {
+    heap: Heap::empty(),
+    brk: 0,
+}));
+
+/// 空间分配错误的回调，直接 panic 退出
+#[alloc_error_handler]
+fn alloc_error_handler(layout: Layout) -> ! {
+    panic!("failed to allocate {:?}", layout)
+}
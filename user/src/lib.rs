@@ -0,0 +1,64 @@
+//! 用户程序的运行时库
+//!
+//! 提供程序入口 `_start`、panic 处理、基于 `brk` 和 `mmap` 的堆分配器，
+//! 内核 `kernel/syscall.rs` 中每个系统调用的封装，以及 [`print!`] 和 [`println!`]。
+//!
+//! `src/bin` 中的每个文件是一个用户程序，定义 `main` 函数作为入口：
+//!
+//! ```ignore
+//! #![no_std]
+//! #![no_main]
+//!
+//! #[macro_use]
+//! extern crate user_lib;
+//!
+//! #[no_mangle]
+//! pub fn main() -> usize {
+//!     println!("Hello world from user mode program!");
+//!     0
+//! }
+//! ```
+#![no_std]
+#![feature(llvm_asm)]
+#![feature(global_asm)]
+#![feature(linkage)]
+#![feature(panic_info_message)]
+#![feature(alloc_error_handler)]
+
+#[macro_use]
+pub mod console;
+pub mod env;
+mod heap;
+mod panic;
+pub mod syscall;
+
+extern crate alloc;
+
+pub use console::{getchar, STDIN, STDOUT};
+pub use syscall::*;
+
+// 程序入口。内核按照 System V 的约定构造初始栈，栈顶依次为 argc、argv 和 envp，
+// 将栈顶地址作为参数交给 `_start_rust`
+global_asm!(
+    "
+    .section .text.entry, \"ax\"
+    .globl _start
+_start:
+    mv a0, sp
+    call _start_rust
+"
+);
+
+/// 初始化参数和环境变量，调用用户程序的 `main`，以其返回值退出
+#[no_mangle]
+extern "C" fn _start_rust(sp: *const usize) -> ! {
+    unsafe { env::init(sp) };
+    sys_exit(main() as isize)
+}
+
+/// 用户程序没有定义 `main` 时链接到这里
+#[linkage = "weak"]
+#[no_mangle]
+fn main() -> usize {
+    panic!("no main() in user program")
+}
@@ -0,0 +1,28 @@
+//! 实现 panic 和 abort 的功能
+
+use crate::syscall::sys_exit;
+use core::panic::PanicInfo;
+
+/// 打印 panic 的信息和位置，以 -1 退出
+#[panic_handler]
+fn panic_handler(info: &PanicInfo) -> ! {
+    match (info.location(), info.message()) {
+        (Some(location), Some(message)) => println!(
+            "\x1b[1;31mpanicked at {}:{}: '{}'\x1b[0m",
+            location.file(),
+            location.line(),
+            message
+        ),
+        (_, Some(message)) => println!("\x1b[1;31mpanic: '{}'\x1b[0m", message),
+        _ => println!("\x1b[1;31mpanic\x1b[0m"),
+    }
+    sys_exit(-1)
+}
+
+/// 终止程序
+///
+/// 调用 [`panic_handler`]
+#[no_mangle]
+extern "C" fn abort() -> ! {
+    panic!("abort()")
+}
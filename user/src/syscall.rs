@@ -0,0 +1,252 @@
+//! 系统调用的封装
+//!
+//! 编号与内核 `kernel/syscall.rs` 中相同。返回 `isize` 的调用出错时返回负的错误码
+
+use alloc::vec::Vec;
+use core::iter::once;
+
+const SYS_UMOUNT: usize = 39;
+const SYS_MOUNT: usize = 40;
+const SYS_READ: usize = 63;
+const SYS_WRITE: usize = 64;
+const SYS_OPEN: usize = 65;
+const SYS_SYNC: usize = 81;
+const SYS_FSYNC: usize = 82;
+const SYS_EXIT: usize = 93;
+const SYS_GETTID: usize = 94;
+const SYS_FORK: usize = 95;
+const SYS_SCHED_YIELD: usize = 124;
+const SYS_GETPID: usize = 172;
+const SYS_GETPPID: usize = 173;
+const SYS_SHM_CREATE: usize = 194;
+const SYS_SHM_ATTACH: usize = 196;
+const SYS_SHM_DETACH: usize = 197;
+const SYS_BRK: usize = 214;
+const SYS_MUNMAP: usize = 215;
+const SYS_CLONE: usize = 220;
+const SYS_EXECVE: usize = 221;
+const SYS_MMAP: usize = 222;
+const SYS_MPROTECT: usize = 226;
+const SYS_WAIT4: usize = 260;
+
+/// 页面可读
+pub const PROT_READ: usize = 1;
+/// 页面可写
+pub const PROT_WRITE: usize = 2;
+/// 页面可执行
+pub const PROT_EXEC: usize = 4;
+
+/// 共享映射，对文件映射的修改会写回文件
+pub const MAP_SHARED: usize = 0x01;
+/// 私有映射，修改只对当前进程可见
+pub const MAP_PRIVATE: usize = 0x02;
+/// 必须映射在给定的地址
+pub const MAP_FIXED: usize = 0x10;
+/// 匿名映射，不对应任何文件
+pub const MAP_ANONYMOUS: usize = 0x20;
+
+/// 页面大小
+pub const PAGE_SIZE: usize = 4096;
+
+/// 没有结束的子进程时 `sys_wait4` 立即返回 0
+pub const WNOHANG: usize = 1;
+
+/// 发起系统调用，a7 为编号，a0 到 a5 为参数，返回值在 a0 中
+fn syscall(id: usize, args: [usize; 6]) -> isize {
+    let ret: isize;
+    unsafe {
+        llvm_asm!("ecall"
+            : "={x10}" (ret)
+            : "{x10}" (args[0]), "{x11}" (args[1]), "{x12}" (args[2]),
+              "{x13}" (args[3]), "{x14}" (args[4]), "{x15}" (args[5]), "{x17}" (id)
+            : "memory"
+            : "volatile"
+        );
+    }
+    ret
+}
+
+/// 转换为以 `\0` 结尾的字符串，供需要 C 字符串的系统调用使用
+fn c_string(string: &str) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(string.len() + 1);
+    bytes.extend_from_slice(string.as_bytes());
+    bytes.push(0);
+    bytes
+}
+
+/// 以空指针结尾的字符串指针数组，`strings` 需要在使用期间保持有效
+fn c_string_array(strings: &[Vec<u8>]) -> Vec<usize> {
+    strings
+        .iter()
+        .map(|string| string.as_ptr() as usize)
+        .chain(once(0))
+        .collect()
+}
+
+/// 从文件中读取数据，返回读到的字节数
+///
+/// 文件暂无数据时返回 0，线程会休眠到数据到来
+pub fn sys_read(fd: usize, buffer: &mut [u8]) -> isize {
+    syscall(
+        SYS_READ,
+        [fd, buffer.as_mut_ptr() as usize, buffer.len(), 0, 0, 0],
+    )
+}
+
+/// 向文件写入数据，返回写入的字节数
+pub fn sys_write(fd: usize, buffer: &[u8]) -> isize {
+    syscall(
+        SYS_WRITE,
+        [fd, buffer.as_ptr() as usize, buffer.len(), 0, 0, 0],
+    )
+}
+
+/// 打开文件，返回文件描述符
+///
+/// 内核从第二个参数开始读取路径及其长度，第一个参数不使用
+pub fn sys_open(path: &str) -> isize {
+    syscall(SYS_OPEN, [0, path.as_ptr() as usize, path.len(), 0, 0, 0])
+}
+
+/// 将 `source` 上类型为 `fstype` 的文件系统挂载到目录 `target`
+pub fn sys_mount(source: &str, target: &str, fstype: &str) -> isize {
+    let (source, target, fstype) = (c_string(source), c_string(target), c_string(fstype));
+    syscall(
+        SYS_MOUNT,
+        [
+            source.as_ptr() as usize,
+            target.as_ptr() as usize,
+            fstype.as_ptr() as usize,
+            0,
+            0,
+            0,
+        ],
+    )
+}
+
+/// 卸载目录 `target` 上的文件系统
+pub fn sys_umount(target: &str) -> isize {
+    let target = c_string(target);
+    syscall(SYS_UMOUNT, [target.as_ptr() as usize, 0, 0, 0, 0, 0])
+}
+
+/// 将所有文件系统中尚未写回的数据写入设备
+pub fn sys_sync() -> isize {
+    syscall(SYS_SYNC, [0; 6])
+}
+
+/// 将文件写回设备
+pub fn sys_fsync(fd: usize) -> isize {
+    syscall(SYS_FSYNC, [fd, 0, 0, 0, 0, 0])
+}
+
+/// 结束当前线程，进程中的最后一个线程结束时进程以 `code` 结束
+pub fn sys_exit(code: isize) -> ! {
+    syscall(SYS_EXIT, [code as usize, 0, 0, 0, 0, 0]);
+    unreachable!()
+}
+
+/// 当前线程的 ID
+pub fn sys_gettid() -> isize {
+    syscall(SYS_GETTID, [0; 6])
+}
+
+/// 在当前进程中复制出一个线程，栈是当前线程的拷贝
+///
+/// 原先的线程得到自身的线程 ID，新线程得到 0
+pub fn sys_fork() -> isize {
+    syscall(SYS_FORK, [0; 6])
+}
+
+/// 创建线程或子进程，参数与 Linux 相同
+///
+/// `flags` 和 `stack` 均为 0 时复制出子进程，父进程得到子进程的 ID，子进程得到 0
+pub fn sys_clone(flags: usize, stack: usize) -> isize {
+    syscall(SYS_CLONE, [flags, stack, 0, 0, 0, 0])
+}
+
+/// 用 `path` 处的程序替换当前进程，成功时不返回
+pub fn sys_execve(path: &str, args: &[&str], envs: &[&str]) -> isize {
+    let path = c_string(path);
+    let args: Vec<Vec<u8>> = args.iter().map(|arg| c_string(arg)).collect();
+    let envs: Vec<Vec<u8>> = envs.iter().map(|env| c_string(env)).collect();
+    let (argv, envp) = (c_string_array(&args), c_string_array(&envs));
+    syscall(
+        SYS_EXECVE,
+        [
+            path.as_ptr() as usize,
+            argv.as_ptr() as usize,
+            envp.as_ptr() as usize,
+            0,
+            0,
+            0,
+        ],
+    )
+}
+
+/// 等待子进程结束并回收，`pid` 为 -1 时等待任意子进程
+///
+/// 返回子进程的 ID，退出码写入 `status` 的第 8 到 15 位
+pub fn sys_wait4(pid: isize, status: &mut i32, options: usize) -> isize {
+    syscall(
+        SYS_WAIT4,
+        [pid as usize, status as *mut i32 as usize, options, 0, 0, 0],
+    )
+}
+
+/// 当前进程的 ID
+pub fn sys_getpid() -> isize {
+    syscall(SYS_GETPID, [0; 6])
+}
+
+/// 父进程的 ID，父进程已经结束且没有交给 init 时为 0
+pub fn sys_getppid() -> isize {
+    syscall(SYS_GETPPID, [0; 6])
+}
+
+/// 让出处理机
+pub fn sys_yield() -> isize {
+    syscall(SYS_SCHED_YIELD, [0; 6])
+}
+
+/// 创建 `size` 字节的共享内存，返回共享内存的 ID
+pub fn sys_shm_create(size: usize) -> isize {
+    syscall(SYS_SHM_CREATE, [size, 0, 0, 0, 0, 0])
+}
+
+/// 将共享内存映射到 `address`，为 0 时由内核选择地址，返回映射的起始地址
+pub fn sys_shm_attach(id: usize, address: usize, prot: usize) -> isize {
+    syscall(SYS_SHM_ATTACH, [id, address, prot, 0, 0, 0])
+}
+
+/// 解除从 `address` 开始的共享内存映射
+pub fn sys_shm_detach(address: usize) -> isize {
+    syscall(SYS_SHM_DETACH, [address, 0, 0, 0, 0, 0])
+}
+
+/// 调整程序断点，返回调整后的断点，`address` 为 0 时只查询
+pub fn sys_brk(address: usize) -> isize {
+    syscall(SYS_BRK, [address, 0, 0, 0, 0, 0])
+}
+
+/// 映射一段内存，参数与 Linux 相同，返回映射的起始地址
+pub fn sys_mmap(
+    address: usize,
+    length: usize,
+    prot: usize,
+    flags: usize,
+    fd: usize,
+    offset: usize,
+) -> isize {
+    syscall(SYS_MMAP, [address, length, prot, flags, fd, offset])
+}
+
+/// 解除一段内存的映射
+pub fn sys_munmap(address: usize, length: usize) -> isize {
+    syscall(SYS_MUNMAP, [address, length, 0, 0, 0, 0])
+}
+
+/// 修改一段内存的权限
+pub fn sys_mprotect(address: usize, length: usize, prot: usize) -> isize {
+    syscall(SYS_MPROTECT, [address, length, prot, 0, 0, 0])
+}